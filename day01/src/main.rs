@@ -1,62 +1,373 @@
-use std::num::ParseIntError;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
 
-fn main() -> Result<(), ParseIntError> {
-    let input = include_str!("../input.txt");
-    let depths = parse_depths(input)?;
+use eyre::{ensure, Context, Result};
+
+// How often to report running counts when sweeping through (possibly huge) inputs
+const PROGRESS_EVERY: usize = 10_000_000;
+
+// Window used when none is given with `--window=N`
+const WINDOW: usize = 3;
+
+fn main() -> Result<()> {
+    let mut path = None;
+    let mut json = false;
+    let mut lenient = false;
+    let mut window = WINDOW;
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--lenient" => lenient = true,
+            _ => match arg.strip_prefix("--window=") {
+                Some(value) => {
+                    window = value
+                        .parse()
+                        .wrap_err_with(|| format!("could not parse `{}` as window size", value))?
+                }
+                None => path = Some(arg),
+            },
+        }
+    }
+
+    // Read from the given file, from stdin if the path is `-`, or fall back to the bundled input
+    let reader: Box<dyn BufRead> = match path.as_deref() {
+        None => Box::new(include_str!("../input.txt").as_bytes()),
+        Some("-") => Box::new(io::stdin().lock()),
+        Some(path) => {
+            let file = File::open(path).wrap_err_with(|| format!("could not open `{}`", path))?;
+            Box::new(BufReader::new(file))
+        }
+    };
+
+    let mut sonar = Sonar::new(window)?;
+    let mut profiler = Profiler::default();
+
+    // Skipped lines are reported as they are found, so that memory does not grow with them
+    let depths: Box<dyn Iterator<Item = Result<usize>>> = if lenient {
+        Box::new(read_depths_lenient(reader, |diagnostic| {
+            eprintln!("skipped line {}: {}", diagnostic.line, diagnostic)
+        }))
+    } else {
+        Box::new(read_depths(reader))
+    };
+
+    for depth in depths {
+        let depth = depth?;
+        let counts = sonar.push(depth);
+        profiler.push(depth);
+
+        if counts.measurements % PROGRESS_EVERY == 0 {
+            eprintln!(
+                "{} measurements, {} increased, {} increased ({}-measurement window)",
+                counts.measurements, counts.increased, counts.increased_with_window, window
+            );
+        }
+    }
+
+    let counts = sonar.counts();
+    let profile = profiler.profile();
+
+    if json {
+        match profile {
+            Some(profile) => println!("{}", profile.to_json()),
+            None => println!("null"),
+        }
+
+        return Ok(());
+    }
 
     println!("--- Day 1: Sonar Sweep ---");
 
-    println!("Measurements that increased: {}", increased(&depths));
+    println!("Measurements that increased: {}", counts.increased);
 
     println!(
-        "Measurements that increased (3-measurement window): {}",
-        increased_with_window(&depths)
+        "Measurements that increased ({}-measurement window): {}",
+        window, counts.increased_with_window
     );
 
+    if let Some(profile) = profile {
+        println!(
+            "Longest increasing run: {} measurements, starting at index {}",
+            profile.longest_increasing_run.len, profile.longest_increasing_run.start
+        );
+        println!("Plateaus: {}", profile.plateaus);
+        println!("Largest jump: {}", profile.max_jump);
+        println!("Largest drop: {}", profile.max_drop);
+        println!(
+            "Depths: min {}, max {}, mean {:.2}",
+            profile.min, profile.max, profile.mean
+        );
+    }
+
     Ok(())
 }
 
-fn parse_depths(input: &str) -> Result<Vec<usize>, ParseIntError> {
-    input
+/// A line that could not be parsed as a depth measurement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Diagnostic {
+    line: usize,
+    column: usize,
+    text: String,
+    reason: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "column {}: {} (`{}`)",
+            self.column, self.reason, self.text
+        )
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+fn parse_depth(line: &str, lineno: usize) -> Result<usize, Diagnostic> {
+    line.parse::<usize>().map_err(|err| {
+        // Point at the first character that cannot be part of a depth; if there is none, the
+        // line is either empty or the depth is too large, and the whole line is at fault
+        let column = line
+            .chars()
+            .enumerate()
+            .position(|(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '+')))
+            .unwrap_or(0);
+
+        Diagnostic {
+            line: lineno,
+            column: column + 1,
+            text: line.to_string(),
+            reason: err.to_string(),
+        }
+    })
+}
+
+fn read_depths(reader: impl BufRead) -> impl Iterator<Item = Result<usize>> {
+    reader.lines().zip(1..).map(|(line, lineno)| {
+        let line = line.wrap_err("could not read input")?;
+        let depth = parse_depth(&line, lineno)
+            .wrap_err_with(|| format!("could not parse line {}", lineno))?;
+        Ok(depth)
+    })
+}
+
+/// Like [`read_depths`], but passes lines that are not depths to `report` and skips them.
+///
+/// I/O errors are still returned, since there is no way to recover from those.
+fn read_depths_lenient<'a>(
+    reader: impl BufRead + 'a,
+    mut report: impl FnMut(Diagnostic) + 'a,
+) -> impl Iterator<Item = Result<usize>> + 'a {
+    reader
         .lines()
-        .map(|line| line.parse::<usize>())
-        .collect::<Result<Vec<_>, _>>()
+        .zip(1..)
+        .filter_map(move |(line, lineno)| match line {
+            Ok(line) => match parse_depth(&line, lineno) {
+                Ok(depth) => Some(Ok(depth)),
+                Err(diagnostic) => {
+                    report(diagnostic);
+                    None
+                }
+            },
+            Err(err) => Some(Err(err).wrap_err("could not read input")),
+        })
 }
 
-fn increased(depths: &[usize]) -> usize {
-    let mut prev = None;
-    let mut increased = 0;
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Counts {
+    measurements: usize,
+    increased: usize,
+    increased_with_window: usize,
+}
 
-    for depth in depths {
-        if let Some(prev) = prev {
-            if depth > prev {
-                increased += 1;
+/// Incrementally counts depth increases, both between single measurements and over a window.
+#[derive(Debug)]
+struct Sonar {
+    measurements: usize,
+    single: Window,
+    window: Window,
+}
+
+impl Sonar {
+    fn new(window: usize) -> Result<Sonar> {
+        Ok(Sonar {
+            measurements: 0,
+            single: Window::new(1)?,
+            window: Window::new(window)?,
+        })
+    }
+
+    fn push(&mut self, depth: usize) -> Counts {
+        self.single.push(depth);
+        self.window.push(depth);
+        self.measurements += 1;
+
+        self.counts()
+    }
+
+    fn counts(&self) -> Counts {
+        Counts {
+            measurements: self.measurements,
+            increased: self.single.increased,
+            increased_with_window: self.window.increased,
+        }
+    }
+}
+
+/// Counts increases between the sums of consecutive `size`-measurement windows.
+///
+/// Only the measurements in the current window are kept, and its sum is rolled forward as new
+/// measurements come in.
+#[derive(Debug)]
+struct Window {
+    size: usize,
+    last: VecDeque<usize>,
+    sum: usize,
+    increased: usize,
+}
+
+impl Window {
+    fn new(size: usize) -> Result<Window> {
+        ensure!(size >= 1, "window size must be at least 1");
+
+        Ok(Window {
+            size,
+            last: VecDeque::with_capacity(size),
+            sum: 0,
+            increased: 0,
+        })
+    }
+
+    fn push(&mut self, depth: usize) -> usize {
+        if self.last.len() == self.size {
+            let oldest = self.last.pop_front().unwrap();
+            let prev = self.sum;
+
+            self.sum = self.sum - oldest + depth;
+
+            if self.sum > prev {
+                self.increased += 1;
             }
+        } else {
+            self.sum += depth;
         }
 
-        prev = Some(depth);
+        self.last.push_back(depth);
+
+        self.increased
     }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Run {
+    start: usize,
+    len: usize,
+}
+
+/// Summary of how the depth evolves over a (non-empty) sequence of measurements.
+#[derive(Debug, Clone, PartialEq)]
+struct DepthProfile {
+    measurements: usize,
+    longest_increasing_run: Run,
+    plateaus: usize,
+    max_jump: usize,
+    max_drop: usize,
+    min: usize,
+    max: usize,
+    mean: f64,
+}
 
-    increased
+impl DepthProfile {
+    fn to_json(&self) -> String {
+        format!(
+            concat!(
+                "{{\"measurements\":{},",
+                "\"longest_increasing_run\":{{\"start\":{},\"len\":{}}},",
+                "\"plateaus\":{},\"max_jump\":{},\"max_drop\":{},",
+                "\"min\":{},\"max\":{},\"mean\":{}}}"
+            ),
+            self.measurements,
+            self.longest_increasing_run.start,
+            self.longest_increasing_run.len,
+            self.plateaus,
+            self.max_jump,
+            self.max_drop,
+            self.min,
+            self.max,
+            self.mean,
+        )
+    }
 }
 
-fn increased_with_window(depths: &[usize]) -> usize {
-    let mut prev = None;
-    let mut increased = 0;
+/// Incrementally builds a [`DepthProfile`], in constant memory.
+#[derive(Debug, Default)]
+struct Profiler {
+    measurements: usize,
+    prev: Option<usize>,
+    run_start: usize,
+    longest_increasing_run: Run,
+    plateaus: usize,
+    max_jump: usize,
+    max_drop: usize,
+    min: usize,
+    max: usize,
+    sum: u128,
+}
 
-    for ((a, b), c) in depths.iter().zip(&depths[1..]).zip(&depths[2..]) {
-        let depth3 = a + b + c;
+impl Profiler {
+    fn push(&mut self, depth: usize) {
+        match self.prev {
+            Some(prev) if depth > prev => self.max_jump = self.max_jump.max(depth - prev),
+            Some(prev) => {
+                if depth == prev {
+                    self.plateaus += 1;
+                } else {
+                    self.max_drop = self.max_drop.max(prev - depth);
+                }
 
-        if let Some(prev) = prev {
-            if depth3 > prev {
-                increased += 1;
+                self.run_start = self.measurements;
+            }
+            None => {
+                self.min = depth;
+                self.max = depth;
             }
         }
 
-        prev = Some(depth3);
+        // On ties, keep the earliest run
+        let run_len = self.measurements - self.run_start + 1;
+        if run_len > self.longest_increasing_run.len {
+            self.longest_increasing_run = Run {
+                start: self.run_start,
+                len: run_len,
+            };
+        }
+
+        self.min = self.min.min(depth);
+        self.max = self.max.max(depth);
+        self.sum += depth as u128;
+        self.measurements += 1;
+        self.prev = Some(depth);
     }
 
-    increased
+    fn profile(&self) -> Option<DepthProfile> {
+        if self.measurements == 0 {
+            return None;
+        }
+
+        Some(DepthProfile {
+            measurements: self.measurements,
+            longest_increasing_run: self.longest_increasing_run,
+            plateaus: self.plateaus,
+            max_jump: self.max_jump,
+            max_drop: self.max_drop,
+            min: self.min,
+            max: self.max,
+            mean: self.sum as f64 / self.measurements as f64,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -64,6 +375,44 @@ mod tests {
     use super::*;
     use indoc::indoc;
 
+    // Slice-based conveniences for when the whole input is already in memory
+    fn parse_depths(input: &str) -> Result<Vec<usize>> {
+        read_depths(input.as_bytes()).collect()
+    }
+
+    fn parse_depths_lenient(input: &str) -> (Vec<usize>, Vec<Diagnostic>) {
+        let mut diagnostics = vec![];
+        let depths = read_depths_lenient(input.as_bytes(), |d| diagnostics.push(d))
+            .collect::<Result<_>>()
+            .expect("reading from a string cannot fail");
+
+        (depths, diagnostics)
+    }
+
+    fn increased(depths: &[usize]) -> usize {
+        increased_with_window(depths, 1).expect("1-measurement windows are always valid")
+    }
+
+    fn increased_with_window(depths: &[usize], window: usize) -> Result<usize> {
+        let mut window = Window::new(window)?;
+
+        for &depth in depths {
+            window.push(depth);
+        }
+
+        Ok(window.increased)
+    }
+
+    fn profile(depths: &[usize]) -> Option<DepthProfile> {
+        let mut profiler = Profiler::default();
+
+        for &depth in depths {
+            profiler.push(depth);
+        }
+
+        profiler.profile()
+    }
+
     #[test]
     fn parses_input() {
         let input = indoc! {"
@@ -80,8 +429,60 @@ mod tests {
         "};
 
         assert_eq!(
-            parse_depths(input),
-            Ok(vec![199, 200, 208, 210, 200, 207, 240, 269, 260, 263,])
+            parse_depths(input).unwrap(),
+            vec![199, 200, 208, 210, 200, 207, 240, 269, 260, 263,]
+        );
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        let input = indoc! {"
+            199
+            200
+            20x8
+        "};
+
+        let err = parse_depths(input).unwrap_err();
+
+        assert_eq!(err.to_string(), "could not parse line 3");
+        assert_eq!(
+            err.downcast_ref::<Diagnostic>(),
+            Some(&Diagnostic {
+                line: 3,
+                column: 3,
+                text: "20x8".to_string(),
+                reason: "invalid digit found in string".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn skips_corrupted_lines_when_lenient() {
+        let input = indoc! {"
+            199
+            200
+
+            208
+            210
+            2#0
+            200
+            207
+            240
+            269
+            99999999999999999999999
+            260
+            263
+        "};
+
+        let (depths, diagnostics) = parse_depths_lenient(input);
+
+        assert_eq!(increased(&depths), 7);
+
+        let skipped: Vec<_> = diagnostics.iter().map(|d| (d.line, d.column)).collect();
+        assert_eq!(skipped, vec![(3, 1), (6, 2), (11, 1)]);
+        assert_eq!(
+            diagnostics[1].to_string(),
+            "column 2: invalid digit found in string (`2#0`)"
         );
     }
 
@@ -96,7 +497,85 @@ mod tests {
     fn example_with_window() {
         let depths = vec![199, 200, 208, 210, 200, 207, 240, 269, 260, 263];
 
-        assert_eq!(increased_with_window(&depths), 5);
+        assert_eq!(increased_with_window(&depths, 1).unwrap(), 7);
+        assert_eq!(increased_with_window(&depths, 3).unwrap(), 5);
+    }
+
+    #[test]
+    fn rejects_empty_windows() {
+        let depths = vec![199, 200, 208];
+
+        assert!(increased_with_window(&depths, 0).is_err());
+        assert!(Sonar::new(0).is_err());
+    }
+
+    #[test]
+    fn matches_naive_windows() {
+        let input = include_str!("../input.txt");
+        let data = parse_depths(input).unwrap();
+
+        for window in [1, 2, 3, 7, 30, data.len(), data.len() + 1] {
+            let sums: Vec<usize> = data.windows(window).map(|w| w.iter().sum()).collect();
+            let naive = sums.windows(2).filter(|pair| pair[1] > pair[0]).count();
+
+            assert_eq!(increased_with_window(&data, window).unwrap(), naive);
+        }
+    }
+
+    #[test]
+    fn reports_running_counts() {
+        let depths = [199, 200, 208, 210, 200];
+        let mut sonar = Sonar::new(3).unwrap();
+
+        let running: Vec<_> = depths
+            .iter()
+            .map(|&depth| {
+                let counts = sonar.push(depth);
+                (counts.increased, counts.increased_with_window)
+            })
+            .collect();
+
+        assert_eq!(running, vec![(0, 0), (1, 0), (2, 0), (3, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn profiles_the_example() {
+        let depths = vec![199, 200, 208, 210, 200, 207, 240, 269, 260, 263];
+
+        assert_eq!(
+            profile(&depths),
+            Some(DepthProfile {
+                measurements: 10,
+                longest_increasing_run: Run { start: 0, len: 4 },
+                plateaus: 0,
+                max_jump: 33,
+                max_drop: 10,
+                min: 199,
+                max: 269,
+                mean: 225.6,
+            })
+        );
+
+        assert_eq!(profile(&[]), None);
+    }
+
+    #[test]
+    fn profiles_plateaus_and_later_runs() {
+        let depths = vec![5, 5, 4, 4, 4, 1, 2, 3, 4, 0];
+        let profile = profile(&depths).unwrap();
+
+        assert_eq!(profile.plateaus, 3);
+        assert_eq!(profile.longest_increasing_run, Run { start: 5, len: 4 });
+        assert_eq!(profile.max_jump, 1);
+        assert_eq!(profile.max_drop, 4);
+
+        assert_eq!(
+            profile.to_json(),
+            concat!(
+                r#"{"measurements":10,"longest_increasing_run":{"start":5,"len":4},"#,
+                r#""plateaus":3,"max_jump":1,"max_drop":4,"min":0,"max":5,"mean":3.2}"#
+            )
+        );
     }
 
     #[test]
@@ -105,6 +584,7 @@ mod tests {
         let data = parse_depths(input).unwrap();
 
         assert_eq!(increased(&data), 1475);
-        assert_eq!(increased_with_window(&data), 1516);
+        assert_eq!(increased_with_window(&data, 1).unwrap(), 1475);
+        assert_eq!(increased_with_window(&data, 3).unwrap(), 1516);
     }
 }
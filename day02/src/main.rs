@@ -1,86 +1,609 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
 use eyre::{bail, ensure, eyre, Context, Result};
 
 fn main() -> Result<()> {
-    println!("--- Day 2: Dive! ---");
+    let mut format = None;
+    let mut model = None;
+    let mut envelope = Envelope::default();
+
+    for arg in std::env::args().skip(1) {
+        if let Some(value) = arg.strip_prefix("--trace=") {
+            format = Some(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--model=") {
+            model = Some(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--min-depth=") {
+            envelope.min_depth = Some(parse_limit(value)?);
+        } else if let Some(value) = arg.strip_prefix("--max-depth=") {
+            envelope.max_depth = Some(parse_limit(value)?);
+        } else if let Some(value) = arg.strip_prefix("--max-aim=") {
+            envelope.max_aim = Some(parse_limit(value)?);
+        } else if arg == "--strict" {
+            envelope.strict = true;
+        } else {
+            bail!("unknown argument `{}`", arg);
+        }
+    }
 
     let input = include_str!("../input.txt");
     let data = parse(input)?;
 
-    println!("Final position: {}", final_position(&data));
-    println!("Final position, fixed: {}", final_position_fixed(&data));
+    if let Some(format) = format {
+        let model = model_by_name(model.as_deref().unwrap_or("fixed"))?;
+        let stdout = io::stdout();
+        let trace = model.trace(&data);
+
+        match format.as_str() {
+            "csv" => write_csv(stdout.lock(), trace)?,
+            "json" => write_json(stdout.lock(), trace)?,
+            _ => bail!("unknown trace format `{}`", format),
+        }
+
+        return Ok(());
+    }
+
+    println!("--- Day 2: Dive! ---");
+
+    if model.is_some() || envelope != Envelope::default() {
+        let name = model.as_deref().unwrap_or("fixed");
+        let outcome = model_by_name(name)?.navigate(&data, &envelope)?;
+
+        for breach in &outcome.breaches {
+            eprintln!("warning: {}", breach);
+        }
+
+        println!("Final position ({}): {}", name, outcome.score);
+        return Ok(());
+    }
+
+    println!("Final position: {}", final_position(&data)?);
+    println!("Final position, fixed: {}", final_position_fixed(&data)?);
 
     Ok(())
 }
 
-#[derive(Debug, PartialEq, Eq)]
+fn parse_limit(value: &str) -> Result<isize> {
+    value
+        .parse()
+        .wrap_err_with(|| format!("could not parse `{}` as limit", value))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Command {
     Forward(isize),
     Down(isize),
     Up(isize),
 }
 
-fn parse(input: &str) -> Result<Vec<Command>> {
-    input
-        .lines()
-        .map(|line| {
-            let mut parts = line.split(' ');
-
-            let command = parts.next().ok_or_else(|| eyre!("missing command"))?;
-            let amount = parts.next().ok_or_else(|| eyre!("missing amount"))?;
-            ensure!(parts.next().is_none(), "found trailing data");
-
-            let amount = amount
-                .parse::<isize>()
-                .wrap_err_with(|| format!("could not parse `{}` as amount", amount))?;
-
-            let cmd = match command {
-                "forward" => Command::Forward(amount),
-                "down" => Command::Down(amount),
-                "up" => Command::Up(amount),
-                _ => bail!("unknown command `{}`", command),
-            };
+impl Command {
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Forward(_) => "forward",
+            Command::Down(_) => "down",
+            Command::Up(_) => "up",
+        }
+    }
 
-            Ok(cmd)
-        })
+    fn amount(&self) -> isize {
+        match *self {
+            Command::Forward(x) | Command::Down(x) | Command::Up(x) => x,
+        }
+    }
+}
+
+/// Commands to execute, along with the lines where they were written. Commands coming from a
+/// macro get the line where the macro was used, which is what ran them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Plan {
+    commands: Vec<Command>,
+    lines: Vec<usize>,
+}
+
+impl Plan {
+    fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    fn push(&mut self, command: Command, line: usize) {
+        self.commands.push(command);
+        self.lines.push(line);
+    }
+
+    fn extend(&mut self, other: &Plan) {
+        self.commands.extend_from_slice(&other.commands);
+        self.lines.extend_from_slice(&other.lines);
+    }
+
+    /// Appends the commands of `other` as if they were all written on `line`.
+    fn extend_at(&mut self, other: &Plan, line: usize) {
+        self.commands.extend_from_slice(&other.commands);
+        self.lines.extend(std::iter::repeat_n(line, other.len()));
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&Command, usize)> {
+        self.commands.iter().zip(self.lines.iter().copied())
+    }
+}
+
+// Guard against scripts whose nested `repeat` blocks would expand into absurd amounts of commands
+const MAX_COMMANDS: usize = 1 << 24;
+
+/// Parses a mission plan into plain commands.
+///
+/// Besides one command per line, plans can have `#` comments, blank lines, `repeat N { ... }`
+/// blocks (which can be nested) and top-level `macro NAME { ... }` definitions, that are then
+/// expanded wherever `NAME` is used in a later line.
+fn parse(input: &str) -> Result<Plan> {
+    let mut lines = input
+        .lines()
         .zip(1..)
-        .map(|(res, lineno)| res.wrap_err_with(|| format!("could not parse line {}", lineno)))
-        .collect()
+        .map(|(line, lineno)| (line.split('#').next().unwrap().trim(), lineno))
+        .filter(|(line, _)| !line.is_empty());
+
+    parse_block(&mut lines, &mut HashMap::new(), None)
 }
 
-fn final_position(data: &[Command]) -> isize {
-    let mut hor = 0;
-    let mut dep = 0;
+fn parse_block<'a>(
+    lines: &mut impl Iterator<Item = (&'a str, usize)>,
+    macros: &mut HashMap<&'a str, Plan>,
+    opened_at: Option<usize>,
+) -> Result<Plan> {
+    let mut commands = Plan::default();
+
+    while let Some((line, lineno)) = lines.next() {
+        let mut parts = line.split_whitespace();
+
+        match parts.next() {
+            Some("}") => {
+                let closing = (|| {
+                    ensure!(opened_at.is_some(), "unmatched `}}`");
+                    ensure!(parts.next().is_none(), "found trailing data");
+                    Ok(())
+                })();
+                closing.wrap_err_with(|| format!("could not parse line {}", lineno))?;
+
+                return Ok(commands);
+            }
+            Some("repeat") => {
+                let times = parse_block_header(parts)
+                    .and_then(|times| {
+                        times
+                            .parse::<usize>()
+                            .wrap_err_with(|| format!("could not parse `{}` as count", times))
+                    })
+                    .wrap_err_with(|| format!("could not parse line {}", lineno))?;
+
+                let body = parse_block(lines, macros, Some(lineno))
+                    .wrap_err_with(|| format!("in repeat block starting at line {}", lineno))?;
 
-    for cmd in data {
-        match cmd {
-            Command::Forward(x) => hor += x,
-            Command::Down(x) => dep += x,
-            Command::Up(x) => dep -= x,
+                let total = body
+                    .len()
+                    .checked_mul(times)
+                    .and_then(|len| len.checked_add(commands.len()))
+                    .filter(|&total| total <= MAX_COMMANDS);
+                ensure!(
+                    total.is_some(),
+                    "repeat block starting at line {} expands to too many commands",
+                    lineno
+                );
+
+                // Empty bodies pass the check above whatever the count, so don't loop over it
+                if !body.is_empty() {
+                    for _ in 0..times {
+                        commands.extend(&body);
+                    }
+                }
+            }
+            Some("macro") => {
+                let name = parse_block_header(parts)
+                    .and_then(|name| {
+                        ensure!(
+                            opened_at.is_none(),
+                            "macros can only be defined at the top level"
+                        );
+                        ensure!(
+                            !["forward", "down", "up", "repeat", "macro"].contains(&name),
+                            "`{}` is a reserved word",
+                            name
+                        );
+                        ensure!(
+                            !macros.contains_key(name),
+                            "macro `{}` already defined",
+                            name
+                        );
+                        Ok(name)
+                    })
+                    .wrap_err_with(|| format!("could not parse line {}", lineno))?;
+
+                let body = parse_block(lines, macros, Some(lineno))
+                    .wrap_err_with(|| format!("in macro `{}` starting at line {}", name, lineno))?;
+
+                macros.insert(name, body);
+            }
+            Some(name) if macros.contains_key(name) && parts.next().is_none() => {
+                commands.extend_at(&macros[name], lineno);
+                ensure!(
+                    commands.len() <= MAX_COMMANDS,
+                    "macro `{}` at line {} expands to too many commands",
+                    name,
+                    lineno
+                );
+            }
+            _ => {
+                let cmd = parse_command(line)
+                    .wrap_err_with(|| format!("could not parse line {}", lineno))?;
+                commands.push(cmd, lineno);
+            }
         }
     }
 
-    dbg!(hor, dep);
-    hor * dep
+    if let Some(opened_at) = opened_at {
+        bail!("block starting at line {} is never closed", opened_at);
+    }
+
+    Ok(commands)
 }
 
-fn final_position_fixed(data: &[Command]) -> isize {
-    let mut hor = 0;
-    let mut dep = 0;
-    let mut aim = 0;
+/// Parses the rest of a `keyword ARG {` line, returning `ARG`.
+fn parse_block_header<'a>(mut parts: impl Iterator<Item = &'a str>) -> Result<&'a str> {
+    let arg = parts.next().ok_or_else(|| eyre!("missing argument"))?;
+    ensure!(parts.next() == Some("{"), "expected `{{` after `{}`", arg);
+    ensure!(parts.next().is_none(), "found trailing data");
+
+    Ok(arg)
+}
+
+fn parse_command(line: &str) -> Result<Command> {
+    let mut parts = line.split_whitespace();
+
+    let command = parts.next().ok_or_else(|| eyre!("missing command"))?;
+    let amount = parts.next().ok_or_else(|| eyre!("missing amount"))?;
+    ensure!(parts.next().is_none(), "found trailing data");
+
+    let amount = amount
+        .parse::<isize>()
+        .wrap_err_with(|| format!("could not parse `{}` as amount", amount))?;
+
+    let cmd = match command {
+        "forward" => Command::Forward(amount),
+        "down" => Command::Down(amount),
+        "up" => Command::Up(amount),
+        _ => bail!("unknown command `{}`", command),
+    };
+
+    Ok(cmd)
+}
 
-    for cmd in data {
-        match cmd {
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    horizontal: isize,
+    depth: isize,
+    aim: isize,
+}
+
+/// How the submarine interprets commands.
+///
+/// Models with custom state must still be able to report a [`Position`] (with `aim` set to zero
+/// if it makes no sense for the model), so that they can be traced and compared to others.
+trait NavigationModel {
+    type State: Copy + Default + Into<Position>;
+
+    /// Updates `state` according to `command`, or returns `None` if that overflows.
+    fn apply(&self, state: &mut Self::State, command: &Command) -> Option<()>;
+
+    fn score(&self, state: &Self::State) -> Option<isize> {
+        let position: Position = (*state).into();
+        position.horizontal.checked_mul(position.depth)
+    }
+}
+
+/// The original (and wrong) understanding of the manual.
+struct Original;
+
+impl NavigationModel for Original {
+    type State = Position;
+
+    fn apply(&self, state: &mut Position, command: &Command) -> Option<()> {
+        match command {
+            Command::Forward(x) => state.horizontal = state.horizontal.checked_add(*x)?,
+            Command::Down(x) => state.depth = state.depth.checked_add(*x)?,
+            Command::Up(x) => state.depth = state.depth.checked_sub(*x)?,
+        }
+
+        Some(())
+    }
+}
+
+/// The fixed understanding of the manual, where `down` and `up` change the aim instead.
+struct Fixed;
+
+impl NavigationModel for Fixed {
+    type State = Position;
+
+    fn apply(&self, state: &mut Position, command: &Command) -> Option<()> {
+        match command {
             Command::Forward(x) => {
-                hor += x;
-                dep += aim * x;
+                state.horizontal = state.horizontal.checked_add(*x)?;
+                state.depth = state.depth.checked_add(state.aim.checked_mul(*x)?)?;
             }
-            Command::Down(x) => aim += x,
-            Command::Up(x) => aim -= x,
+            Command::Down(x) => state.aim = state.aim.checked_add(*x)?,
+            Command::Up(x) => state.aim = state.aim.checked_sub(*x)?,
         }
+
+        Some(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Limit {
+    MinDepth(isize),
+    MaxDepth(isize),
+    MaxAim(isize),
+}
+
+/// Where and how the submarine first went outside its safety envelope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Breach {
+    limit: Limit,
+    step: usize,
+    line: usize,
+    value: isize,
+}
+
+impl fmt::Display for Breach {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (what, limit) = match self.limit {
+            Limit::MinDepth(limit) => ("depth", format!("below minimum of {}", limit)),
+            Limit::MaxDepth(limit) => ("depth", format!("above maximum of {}", limit)),
+            Limit::MaxAim(limit) => ("aim", format!("above maximum of {}", limit)),
+        };
+
+        write!(
+            f,
+            "{} of {} at step {} (line {}) is {}",
+            what, self.value, self.step, self.line, limit
+        )
+    }
+}
+
+/// Limits the submarine should stay within.
+///
+/// When `strict`, leaving the envelope is an error; otherwise, the first breach of each limit is
+/// reported, but navigation continues.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Envelope {
+    min_depth: Option<isize>,
+    max_depth: Option<isize>,
+    max_aim: Option<isize>,
+    strict: bool,
+}
+
+impl Envelope {
+    fn breached(&self, position: &Position) -> impl Iterator<Item = (Limit, isize)> {
+        let min_depth = self
+            .min_depth
+            .filter(|&limit| position.depth < limit)
+            .map(|limit| (Limit::MinDepth(limit), position.depth));
+        let max_depth = self
+            .max_depth
+            .filter(|&limit| position.depth > limit)
+            .map(|limit| (Limit::MaxDepth(limit), position.depth));
+        let max_aim = self
+            .max_aim
+            .filter(|&limit| position.aim > limit)
+            .map(|limit| (Limit::MaxAim(limit), position.aim));
+
+        min_depth.into_iter().chain(max_depth).chain(max_aim)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NavigationError {
+    Overflow { step: usize, line: usize },
+    ScoreOverflow,
+    OutsideEnvelope(Breach),
+}
+
+impl fmt::Display for NavigationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NavigationError::Overflow { step, line } => {
+                write!(f, "arithmetic overflow at step {} (line {})", step, line)
+            }
+            NavigationError::ScoreOverflow => write!(f, "arithmetic overflow computing the score"),
+            NavigationError::OutsideEnvelope(breach) => write!(f, "{}", breach),
+        }
+    }
+}
+
+impl std::error::Error for NavigationError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Outcome {
+    score: isize,
+    breaches: Vec<Breach>,
+}
+
+fn navigate<M: NavigationModel>(
+    data: &Plan,
+    model: &M,
+    envelope: &Envelope,
+) -> Result<Outcome, NavigationError> {
+    let mut last = M::State::default();
+    let mut breaches: Vec<Breach> = vec![];
+
+    for step in trace(data, model) {
+        let step = step?;
+
+        for (limit, value) in envelope.breached(&step.position()) {
+            let breach = Breach {
+                limit,
+                step: step.step,
+                line: step.line,
+                value,
+            };
+
+            if envelope.strict {
+                return Err(NavigationError::OutsideEnvelope(breach));
+            }
+
+            if breaches.iter().all(|b| b.limit != limit) {
+                breaches.push(breach);
+            }
+        }
+
+        last = step.state;
+    }
+
+    let score = model.score(&last).ok_or(NavigationError::ScoreOverflow)?;
+
+    Ok(Outcome { score, breaches })
+}
+
+/// The state of the submarine after some `step` (starting at 1) executed `command`, written at
+/// `line`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TraceStep<'a, S = Position> {
+    step: usize,
+    line: usize,
+    command: &'a Command,
+    state: S,
+}
+
+impl<'a, S: Copy + Into<Position>> TraceStep<'a, S> {
+    fn position(&self) -> Position {
+        self.state.into()
+    }
+
+    fn erased(&self) -> TraceStep<'a> {
+        TraceStep {
+            step: self.step,
+            line: self.line,
+            command: self.command,
+            state: self.position(),
+        }
+    }
+}
+
+/// Yields the state after each command, stopping after the first overflow.
+fn trace<'a, M: NavigationModel>(
+    data: &'a Plan,
+    model: &'a M,
+) -> impl Iterator<Item = Result<TraceStep<'a, M::State>, NavigationError>> {
+    let mut state = M::State::default();
+    let mut overflowed = false;
+
+    data.iter()
+        .zip(1..)
+        .map_while(move |((command, line), step)| {
+            if overflowed {
+                return None;
+            }
+
+            if model.apply(&mut state, command).is_none() {
+                overflowed = true;
+                return Some(Err(NavigationError::Overflow { step, line }));
+            }
+
+            Some(Ok(TraceStep {
+                step,
+                line,
+                command,
+                state,
+            }))
+        })
+}
+
+type DynTrace<'a> = Box<dyn Iterator<Item = Result<TraceStep<'a>, NavigationError>> + 'a>;
+
+/// Object-safe counterpart to [`NavigationModel`], so that models can be picked at runtime.
+trait Navigator {
+    fn navigate(&self, data: &Plan, envelope: &Envelope) -> Result<Outcome, NavigationError>;
+
+    fn trace<'a>(&'a self, data: &'a Plan) -> DynTrace<'a>;
+}
+
+impl<M: NavigationModel> Navigator for M {
+    fn navigate(&self, data: &Plan, envelope: &Envelope) -> Result<Outcome, NavigationError> {
+        navigate(data, self, envelope)
+    }
+
+    fn trace<'a>(&'a self, data: &'a Plan) -> DynTrace<'a> {
+        Box::new(trace(data, self).map(|step| step.map(|step| step.erased())))
+    }
+}
+
+fn model_by_name(name: &str) -> Result<Box<dyn Navigator>> {
+    match name {
+        "original" => Ok(Box::new(Original)),
+        "fixed" => Ok(Box::new(Fixed)),
+        _ => bail!("unknown navigation model `{}`", name),
     }
+}
+
+fn write_csv<'a>(
+    mut out: impl Write,
+    trace: impl Iterator<Item = Result<TraceStep<'a>, NavigationError>>,
+) -> Result<()> {
+    writeln!(out, "step,command,amount,horizontal,depth,aim")?;
+
+    for s in trace {
+        let s = s?;
+
+        writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            s.step,
+            s.command.name(),
+            s.command.amount(),
+            s.state.horizontal,
+            s.state.depth,
+            s.state.aim
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_json<'a>(
+    mut out: impl Write,
+    trace: impl Iterator<Item = Result<TraceStep<'a>, NavigationError>>,
+) -> Result<()> {
+    write!(out, "[")?;
+
+    for (i, s) in trace.enumerate() {
+        let s = s?;
+
+        write!(
+            out,
+            r#"{}{{"step":{},"command":"{}","amount":{},"horizontal":{},"depth":{},"aim":{}}}"#,
+            if i == 0 { "\n  " } else { ",\n  " },
+            s.step,
+            s.command.name(),
+            s.command.amount(),
+            s.state.horizontal,
+            s.state.depth,
+            s.state.aim
+        )?;
+    }
+
+    writeln!(out, "\n]")?;
+
+    Ok(())
+}
+
+fn final_position(data: &Plan) -> Result<isize, NavigationError> {
+    navigate(data, &Original, &Envelope::default()).map(|outcome| outcome.score)
+}
 
-    dbg!(hor, dep, aim);
-    hor * dep
+fn final_position_fixed(data: &Plan) -> Result<isize, NavigationError> {
+    navigate(data, &Fixed, &Envelope::default()).map(|outcome| outcome.score)
 }
 
 #[cfg(test)]
@@ -102,21 +625,324 @@ mod tests {
         use Command::*;
 
         assert_eq!(
-            parse(SAMPLE).unwrap(),
+            parse(SAMPLE).unwrap().commands,
             vec![Forward(5), Down(5), Forward(8), Up(3), Down(8), Forward(2)]
         );
     }
 
+    #[test]
+    fn parses_scripts() {
+        use Command::*;
+
+        let script = indoc! {"
+            # Leave the dock
+            forward 5
+
+            macro dive {
+                down 5  # steeply
+                forward 8
+            }
+
+            repeat 2 {
+                dive
+                repeat 3 {
+                    up 1
+                }
+            }
+            forward 2
+        "};
+
+        assert_eq!(
+            parse(script).unwrap().commands,
+            vec![
+                Forward(5),
+                Down(5),
+                Forward(8),
+                Up(1),
+                Up(1),
+                Up(1),
+                Down(5),
+                Forward(8),
+                Up(1),
+                Up(1),
+                Up(1),
+                Forward(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_errors_inside_nested_blocks() {
+        let script = indoc! {"
+            forward 5
+            repeat 2 {
+                down 1
+                repeat 3 {
+                    sideways 1
+                }
+            }
+        "};
+
+        let err = parse(script).unwrap_err();
+        let chain: Vec<_> = err.chain().map(|e| e.to_string()).collect();
+
+        assert_eq!(
+            chain,
+            vec![
+                "in repeat block starting at line 2",
+                "in repeat block starting at line 4",
+                "could not parse line 5",
+                "unknown command `sideways`",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_blocks() {
+        let unclosed = "repeat 2 {\n    forward 1\n";
+        let unmatched = "forward 1\n}\n";
+        let unknown_macro = "dive\n";
+        let nested_macro = "repeat 2 {\n    macro dive {\n    }\n}\n";
+        let huge = "repeat 100000 {\n    repeat 100000 {\n        up 1\n    }\n}\n";
+
+        for script in [unclosed, unmatched, unknown_macro, nested_macro, huge] {
+            assert!(parse(script).is_err(), "{:?}", script);
+        }
+
+        // Empty blocks are fine, however many times they are repeated
+        let empty = "repeat 18446744073709551615 {\n}\nforward 1\n";
+        assert_eq!(parse(empty).unwrap().commands, vec![Command::Forward(1)]);
+    }
+
     #[test]
     fn solves_the_first_example() {
         let data = parse(SAMPLE).unwrap();
-        assert_eq!(final_position(&data), 150);
+        assert_eq!(final_position(&data).unwrap(), 150);
     }
 
     #[test]
     fn solves_the_second_example() {
         let data = parse(SAMPLE).unwrap();
-        assert_eq!(final_position_fixed(&data), 900);
+        assert_eq!(final_position_fixed(&data).unwrap(), 900);
+    }
+
+    #[test]
+    fn traces_both_models() {
+        let data = parse(SAMPLE).unwrap();
+
+        let states = |model: &dyn Navigator| -> Vec<_> {
+            model
+                .trace(&data)
+                .map(|s| s.unwrap())
+                .map(|s| (s.step, s.state.horizontal, s.state.depth, s.state.aim))
+                .collect()
+        };
+
+        assert_eq!(
+            states(&Original),
+            vec![
+                (1, 5, 0, 0),
+                (2, 5, 5, 0),
+                (3, 13, 5, 0),
+                (4, 13, 2, 0),
+                (5, 13, 10, 0),
+                (6, 15, 10, 0),
+            ]
+        );
+
+        assert_eq!(
+            states(&Fixed),
+            vec![
+                (1, 5, 0, 0),
+                (2, 5, 0, 5),
+                (3, 13, 40, 5),
+                (4, 13, 40, 2),
+                (5, 13, 40, 10),
+                (6, 15, 60, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn exports_traces() {
+        let data = parse("forward 5\ndown 5\nforward 8\n").unwrap();
+
+        let mut csv = vec![];
+        write_csv(&mut csv, trace(&data, &Fixed)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            indoc! {"
+                step,command,amount,horizontal,depth,aim
+                1,forward,5,5,0,0
+                2,down,5,5,0,5
+                3,forward,8,13,40,5
+            "}
+        );
+
+        let mut json = vec![];
+        write_json(&mut json, trace(&data, &Original).take(2)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            indoc! {r#"
+                [
+                  {"step":1,"command":"forward","amount":5,"horizontal":5,"depth":0,"aim":0},
+                  {"step":2,"command":"down","amount":5,"horizontal":5,"depth":5,"aim":0}
+                ]
+            "#}
+        );
+
+        let mut empty = vec![];
+        write_json(&mut empty, trace(&Plan::default(), &Original)).unwrap();
+        assert_eq!(String::from_utf8(empty).unwrap(), "[\n]\n");
+    }
+
+    #[test]
+    fn supports_custom_models() {
+        // Like the fixed model, but the submarine cannot dive deeper than some maximum depth
+        struct MaxDepth(isize);
+
+        impl NavigationModel for MaxDepth {
+            type State = Position;
+
+            fn apply(&self, state: &mut Position, command: &Command) -> Option<()> {
+                Fixed.apply(state, command)?;
+                state.depth = state.depth.min(self.0);
+                Some(())
+            }
+        }
+
+        let data = parse(SAMPLE).unwrap();
+        let no_limits = Envelope::default();
+
+        assert_eq!(
+            navigate(&data, &MaxDepth(isize::MAX), &no_limits)
+                .unwrap()
+                .score,
+            900
+        );
+        assert_eq!(
+            navigate(&data, &MaxDepth(50), &no_limits).unwrap().score,
+            15 * 50
+        );
+    }
+
+    #[test]
+    fn picks_models_by_name() {
+        let data = parse(SAMPLE).unwrap();
+        let no_limits = Envelope::default();
+
+        assert_eq!(
+            model_by_name("original")
+                .unwrap()
+                .navigate(&data, &no_limits)
+                .unwrap()
+                .score,
+            150
+        );
+        assert_eq!(
+            model_by_name("fixed")
+                .unwrap()
+                .navigate(&data, &no_limits)
+                .unwrap()
+                .score,
+            900
+        );
+        assert!(model_by_name("warp").is_err());
+    }
+
+    #[test]
+    fn reports_overflows_with_their_lines() {
+        let script = indoc! {"
+            # Dive really steeply
+            down 9223372036854775807
+            forward 1
+            forward 2
+        "};
+
+        let data = parse(script).unwrap();
+
+        assert_eq!(
+            final_position_fixed(&data),
+            Err(NavigationError::Overflow { step: 3, line: 4 })
+        );
+        assert_eq!(final_position(&data), Err(NavigationError::ScoreOverflow));
+
+        let steps: Vec<_> = trace(&data, &Fixed).map(|s| s.is_ok()).collect();
+        assert_eq!(steps, vec![true, true, false]);
+    }
+
+    #[test]
+    fn reports_overflows_inside_macros_at_their_call() {
+        let script = indoc! {"
+            macro plunge {
+                down 9223372036854775807
+                forward 2
+            }
+
+            forward 1
+            repeat 2 {
+                plunge
+            }
+        "};
+
+        let data = parse(script).unwrap();
+
+        assert_eq!(data.lines, vec![6, 8, 8, 8, 8]);
+        assert_eq!(
+            final_position_fixed(&data),
+            Err(NavigationError::Overflow { step: 3, line: 8 })
+        );
+    }
+
+    #[test]
+    fn enforces_the_safety_envelope() {
+        let script = indoc! {"
+            forward 5
+            up 5
+            forward 8
+            down 8
+            forward 2
+            forward 9
+        "};
+
+        let data = parse(script).unwrap();
+        let envelope = Envelope {
+            min_depth: Some(0),
+            max_aim: Some(2),
+            ..Envelope::default()
+        };
+
+        let outcome = navigate(&data, &Fixed, &envelope).unwrap();
+        assert_eq!(outcome.score, 24 * -7);
+        assert_eq!(
+            outcome.breaches,
+            vec![
+                Breach {
+                    limit: Limit::MinDepth(0),
+                    step: 3,
+                    line: 3,
+                    value: -40
+                },
+                Breach {
+                    limit: Limit::MaxAim(2),
+                    step: 4,
+                    line: 4,
+                    value: 3
+                },
+            ]
+        );
+
+        let strict = Envelope {
+            strict: true,
+            ..envelope
+        };
+        let err = navigate(&data, &Fixed, &strict).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "depth of -40 at step 3 (line 3) is below minimum of 0"
+        );
     }
 
     #[test]
@@ -124,7 +950,7 @@ mod tests {
         let input = include_str!("../input.txt");
         let data = parse(input).unwrap();
 
-        assert_eq!(final_position(&data), 2027977);
-        assert_eq!(final_position_fixed(&data), 1903644897);
+        assert_eq!(final_position(&data).unwrap(), 2027977);
+        assert_eq!(final_position_fixed(&data).unwrap(), 1903644897);
     }
 }
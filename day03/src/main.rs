@@ -1,61 +1,210 @@
-use eyre::{Context, Result};
+use std::cmp::Ordering;
+use std::fmt;
+
+use eyre::{bail, ensure, eyre, Context, Result};
 
 fn main() -> Result<()> {
+    let mut o2criteria = BitCriteria::OXYGEN_GENERATOR;
+    let mut co2criteria = BitCriteria::CO2_SCRUBBER;
+    let mut explain = false;
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--fail-on-ties" => {
+                o2criteria.tie = Tie::Fail;
+                co2criteria.tie = Tie::Fail;
+            }
+            "--lsb-first" => {
+                o2criteria.order = ScanOrder::LsbFirst;
+                co2criteria.order = ScanOrder::LsbFirst;
+            }
+            "--explain" => explain = true,
+            _ => bail!("unknown argument `{}`", arg),
+        }
+    }
+
     println!("--- Day 3: Binary Diagnostic ---");
 
     let input = include_str!("../input.txt");
-    let mut data = parse(input)?;
+    let data = parse(input)?;
 
-    println!("Power consumption: {}", power_consumption(&data));
-    println!("Life support rating: {}", life_support_rating(&mut data));
+    println!("Power consumption: {}", power_consumption(&data)?);
+    println!("Life support rating: {}", life_support_rating(&data)?);
+
+    if (o2criteria, co2criteria) != (BitCriteria::OXYGEN_GENERATOR, BitCriteria::CO2_SCRUBBER) {
+        println!(
+            "Life support rating, with custom criteria: {}",
+            life_support_rating_with(&data, o2criteria, co2criteria)?
+        );
+    }
+
+    if explain {
+        println!();
+        print!("{}", Explanation::new(&data, o2criteria, co2criteria)?);
+    }
 
     Ok(())
 }
 
-type Value = u16;
+/// Diagnostic values of a fixed bit width.
+///
+/// Values are stored back to back as big-endian `u64` words, so that any width is supported.
+/// Since the most significant word comes first, comparing the words of two values is the same as
+/// comparing them numerically.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Diagnostics {
+    width: usize,
+    words: usize,
+    data: Vec<u64>,
+}
+
+impl Diagnostics {
+    fn new(width: usize) -> Diagnostics {
+        assert!(width > 0);
 
-fn parse(input: &str) -> Result<Vec<Value>> {
-    input
-        .lines()
-        .map(|line| {
-            Value::from_str_radix(line, 2).wrap_err_with(|| format!("could not parse: {}", line))
-        })
-        .zip(1..)
-        .map(|(res, lineno): (Result<Value>, usize)| {
-            res.wrap_err_with(|| format!("could not parse line {}", lineno))
-        })
+        Diagnostics {
+            width,
+            words: width.div_ceil(64),
+            data: vec![],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.data.len() / self.words
+    }
+
+    fn values(&self) -> impl Iterator<Item = &[u64]> {
+        self.data.chunks_exact(self.words)
+    }
+}
+
+fn bit(value: &[u64], bit: usize) -> bool {
+    value[value.len() - 1 - bit / 64] & (1 << (bit % 64)) != 0
+}
+
+fn set_bit(value: &mut [u64], bit: usize) {
+    let word = value.len() - 1 - bit / 64;
+    value[word] |= 1 << (bit % 64);
+}
+
+fn to_u128(value: &[u64]) -> Option<u128> {
+    let (high, low) = value.split_at(value.len().saturating_sub(2));
+
+    if high.iter().any(|&word| word != 0) {
+        return None;
+    }
+
+    Some(low.iter().fold(0, |acc, &word| acc << 64 | word as u128))
+}
+
+fn to_binary(value: &[u64], width: usize) -> String {
+    (0..width)
+        .rev()
+        .map(|b| if bit(value, b) { '1' } else { '0' })
         .collect()
 }
 
-fn power_consumption(data: &[Value]) -> usize {
-    let mut gamma = 0;
-    let mut epsilon = 0;
-    let half = data.len() / 2;
+fn rating(a: &[u64], b: &[u64]) -> Result<u128> {
+    to_u128(a)
+        .zip(to_u128(b))
+        .and_then(|(a, b)| a.checked_mul(b))
+        .ok_or_else(|| eyre!("rating does not fit in 128 bits"))
+}
+
+fn parse(input: &str) -> Result<Diagnostics> {
+    let width = input.lines().next().map_or(0, str::len);
+    ensure!(width > 0, "report is empty");
+
+    let mut data = Diagnostics::new(width);
+
+    for (line, lineno) in input.lines().zip(1..) {
+        let mut value = vec![0; data.words];
 
-    for count in bit_counts(data).into_iter().rev() {
-        gamma <<= 1;
-        epsilon <<= 1;
+        (|| {
+            ensure!(
+                line.len() == width,
+                "expected {} bits but found {}",
+                width,
+                line.len()
+            );
+
+            for (i, digit) in line.bytes().enumerate() {
+                match digit {
+                    b'0' => {}
+                    b'1' => set_bit(&mut value, width - 1 - i),
+                    _ => bail!("could not parse: {}", line),
+                }
+            }
 
+            Ok(())
+        })()
+        .wrap_err_with(|| format!("could not parse line {}", lineno))?;
+
+        data.data.extend(value);
+    }
+
+    Ok(data)
+}
+
+fn power_consumption(data: &Diagnostics) -> Result<u128> {
+    let (gamma, epsilon) = gamma_and_epsilon(data, &bit_counts(data));
+
+    eprintln!(
+        "gamma = {}, epsilon = {}",
+        to_binary(&gamma, data.width),
+        to_binary(&epsilon, data.width)
+    );
+    rating(&gamma, &epsilon)
+}
+
+fn gamma_and_epsilon(data: &Diagnostics, counts: &[usize]) -> (Vec<u64>, Vec<u64>) {
+    let mut gamma = vec![0; data.words];
+    let mut epsilon = vec![0; data.words];
+    let half = data.len() / 2;
+
+    for (bit, &count) in counts.iter().enumerate() {
         if count > half {
-            gamma |= 1;
-        } else if count > 0 {
-            epsilon |= 1;
+            set_bit(&mut gamma, bit);
         } else {
-            // Bit not in use, ignore
+            set_bit(&mut epsilon, bit);
         }
     }
 
-    eprintln!("gamma = {:b}, epsilon = {:b}", gamma, epsilon);
-    gamma * epsilon
+    (gamma, epsilon)
 }
 
-fn bit_counts(data: &[Value]) -> [usize; Value::BITS as _] {
-    let mut counts = [0usize; Value::BITS as _];
+/// Counts how many values have each bit set.
+///
+/// Values are processed 64 at a time: each block of (words of) values is transposed, so that
+/// each bit ends up in a word of its own, and the counts are then just population counts.
+fn bit_counts(data: &Diagnostics) -> Vec<usize> {
+    let mut counts = vec![0usize; data.width];
+    let mut block = [0u64; 64];
+
+    for w in 0..data.words {
+        // Words are big-endian, so the last word holds the least significant bits
+        let lowest = (data.words - 1 - w) * 64;
+        let bits = (data.width - lowest).min(64);
+
+        let mut column = data.data.iter().skip(w).step_by(data.words);
+
+        loop {
+            let mut n = 0;
+
+            for (slot, &word) in block.iter_mut().zip(&mut column) {
+                *slot = word;
+                n += 1;
+            }
+
+            if n == 0 {
+                break;
+            }
+
+            block[n..].fill(0);
+            transpose(&mut block);
 
-    for report in data {
-        for (bit, count) in counts.iter_mut().enumerate() {
-            if report & (1 << bit) != 0 {
-                *count += 1;
+            for (count, bits) in counts[lowest..lowest + bits].iter_mut().zip(&block) {
+                *count += bits.count_ones() as usize;
             }
         }
     }
@@ -63,32 +212,164 @@ fn bit_counts(data: &[Value]) -> [usize; Value::BITS as _] {
     counts
 }
 
-fn life_support_rating(data: &mut [Value]) -> usize {
-    data.sort_unstable();
+/// Transposes a 64x64 bit matrix, where bit `j` of `m[i]` is the element at row `i` and column
+/// `j`, by recursively swapping the off-diagonal blocks (see Hacker's Delight, 7-3).
+fn transpose(m: &mut [u64; 64]) {
+    let mut j = 32;
+    let mut mask: u64 = 0x0000_0000_ffff_ffff;
+
+    while j != 0 {
+        let mut k = 0;
+
+        while k < 64 {
+            let t = ((m[k] >> j) ^ m[k + j]) & mask;
+            m[k] ^= t << j;
+            m[k + j] ^= t;
+            k = (k + j + 1) & !j;
+        }
+
+        j >>= 1;
+        mask ^= mask << j;
+    }
+}
+
+fn life_support_rating(data: &Diagnostics) -> Result<u128> {
+    life_support_rating_with(
+        data,
+        BitCriteria::OXYGEN_GENERATOR,
+        BitCriteria::CO2_SCRUBBER,
+    )
+}
+
+fn life_support_rating_with(
+    data: &Diagnostics,
+    o2criteria: BitCriteria,
+    co2criteria: BitCriteria,
+) -> Result<u128> {
+    let o2sorted = sort_for_scan(data, o2criteria.order);
+    let o2generation = find_with(o2criteria, data.width, &o2sorted)?;
 
-    let o2generation = find_with(BitCriteria::WithMostCommonBits, data);
-    let co2scrubbing = find_with(BitCriteria::WithLeastCommonBits, data);
+    let co2scrubbing = if co2criteria.order == o2criteria.order {
+        find_with(co2criteria, data.width, &o2sorted)?
+    } else {
+        find_with(
+            co2criteria,
+            data.width,
+            &sort_for_scan(data, co2criteria.order),
+        )?
+    };
 
     eprintln!(
-        "o2generation = {:b}, co2scrubbing = {:b}",
-        o2generation, co2scrubbing
+        "o2generation = {}, co2scrubbing = {}",
+        to_binary(o2generation, data.width),
+        to_binary(co2scrubbing, data.width)
     );
-    o2generation as usize * co2scrubbing as usize
+    rating(o2generation, co2scrubbing)
 }
 
-enum BitCriteria {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Keep {
     WithMostCommonBits,
     WithLeastCommonBits,
 }
 
+/// Which values to keep when as many have a bit set to one as to zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tie {
+    PreferOne,
+    PreferZero,
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BitCriteria {
+    keep: Keep,
+    tie: Tie,
+    order: ScanOrder,
+}
+
+impl BitCriteria {
+    const OXYGEN_GENERATOR: BitCriteria = BitCriteria {
+        keep: Keep::WithMostCommonBits,
+        tie: Tie::PreferOne,
+        order: ScanOrder::MsbFirst,
+    };
+
+    const CO2_SCRUBBER: BitCriteria = BitCriteria {
+        keep: Keep::WithLeastCommonBits,
+        tie: Tie::PreferZero,
+        order: ScanOrder::MsbFirst,
+    };
+}
+
+/// Sorts values so that they can be filtered by [`find_with`] in the given scan `order`.
+///
+/// For `LsbFirst` values are ordered as if their bits had been reversed.
+fn sort_for_scan(data: &Diagnostics, order: ScanOrder) -> Vec<&[u64]> {
+    let mut sorted: Vec<_> = data.values().collect();
+
+    match order {
+        ScanOrder::MsbFirst => sorted.sort_unstable(),
+        ScanOrder::LsbFirst => sorted.sort_unstable_by(|x, y| {
+            let x = (0..data.width).map(|b| bit(x, b));
+            let y = (0..data.width).map(|b| bit(y, b));
+            x.cmp(y)
+        }),
+    }
+
+    sorted
+}
+
 /// Finds values of interest by successively filtering using most/least bit criteria.
 ///
-/// As the name implies, `sorted_data` must be sorted, otherwise the returned value will be
-/// meaningless.
-fn find_with(criteria: BitCriteria, sorted_data: &[Value]) -> Value {
+/// As the name implies, `sorted_data` must be sorted (see [`sort_for_scan`]) in the order the
+/// bits are scanned, otherwise the returned value will be meaningless.
+fn find_with<'a>(
+    criteria: BitCriteria,
+    width: usize,
+    sorted_data: &[&'a [u64]],
+) -> Result<&'a [u64]> {
+    find_with_rounds(criteria, width, sorted_data).map(|(value, _)| value)
+}
+
+/// Which values were kept after filtering by some bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kept {
+    Zeros,
+    Ones,
+    All,
+}
+
+/// How the remaining values were filtered by a single bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Round {
+    bit: usize,
+    zeros: usize,
+    ones: usize,
+    kept: Kept,
+}
+
+/// Like [`find_with`], but also returns how the values were filtered in each round.
+fn find_with_rounds<'a>(
+    criteria: BitCriteria,
+    width: usize,
+    sorted_data: &[&'a [u64]],
+) -> Result<(&'a [u64], Vec<Round>)> {
     let mut rest = sorted_data;
+    let mut rounds = vec![];
+
+    let bits: Box<dyn Iterator<Item = usize>> = match criteria.order {
+        ScanOrder::MsbFirst => Box::new((0..width).rev()),
+        ScanOrder::LsbFirst => Box::new(0..width),
+    };
 
-    for bit in (0..Value::BITS).rev() {
+    for b in bits {
         if rest.len() == 1 {
             // Found it, done
             break;
@@ -96,41 +377,165 @@ fn find_with(criteria: BitCriteria, sorted_data: &[Value]) -> Value {
 
         // Since `rest` is sorted, all values before index `p` have this bit set to zero, and all
         // values with indices equal or above `p` have this bit set to one
-        let p = rest.partition_point(|&x| x & (1 << bit) == 0);
+        let p = rest.partition_point(|x| !bit(x, b));
         let have_zero = &rest[..p];
         let have_one = &rest[p..];
 
-        if p == rest.len() {
-            // Bit not in use, ignore
+        let mut round = Round {
+            bit: b,
+            zeros: have_zero.len(),
+            ones: have_one.len(),
+            kept: Kept::All,
+        };
+
+        if have_zero.is_empty() || have_one.is_empty() {
+            // All remaining values agree on this bit, so there is nothing to filter
+            rounds.push(round);
             continue;
         }
 
         // Successively pick the partition with this bit set to the most/least common value
-        match criteria {
-            BitCriteria::WithMostCommonBits => {
-                if have_one.len() >= have_zero.len() {
-                    rest = have_one;
-                } else {
-                    rest = have_zero;
-                }
-            }
-            BitCriteria::WithLeastCommonBits => {
-                if have_one.len() >= have_zero.len() {
-                    rest = have_zero;
-                } else {
-                    rest = have_one;
-                }
-            }
+        let keep_ones = match have_one.len().cmp(&have_zero.len()) {
+            Ordering::Greater => criteria.keep == Keep::WithMostCommonBits,
+            Ordering::Less => criteria.keep == Keep::WithLeastCommonBits,
+            Ordering::Equal => match criteria.tie {
+                Tie::PreferOne => true,
+                Tie::PreferZero => false,
+                Tie::Fail => bail!(
+                    "ambiguous bit {}: {} remaining values have it set and {} do not",
+                    b,
+                    have_one.len(),
+                    have_zero.len()
+                ),
+            },
+        };
+
+        if keep_ones {
+            rest = have_one;
+            round.kept = Kept::Ones;
+        } else {
+            rest = have_zero;
+            round.kept = Kept::Zeros;
         }
+
+        rounds.push(round);
     }
 
-    assert_eq!(
-        rest.len(),
-        1,
-        "bit filter exhausted but slice still has more than one element"
+    ensure!(
+        rest.len() == 1,
+        "bit filter exhausted but {} values remain",
+        rest.len()
     );
 
-    rest[0]
+    Ok((rest[0], rounds))
+}
+
+/// Step by step account of how the power consumption and life support ratings were derived.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Explanation {
+    width: usize,
+    len: usize,
+    bit_counts: Vec<usize>,
+    gamma: String,
+    epsilon: String,
+    o2rounds: Vec<Round>,
+    o2generation: String,
+    co2rounds: Vec<Round>,
+    co2scrubbing: String,
+}
+
+impl Explanation {
+    fn new(
+        data: &Diagnostics,
+        o2criteria: BitCriteria,
+        co2criteria: BitCriteria,
+    ) -> Result<Explanation> {
+        let bit_counts = bit_counts(data);
+        let (gamma, epsilon) = gamma_and_epsilon(data, &bit_counts);
+
+        let o2sorted = sort_for_scan(data, o2criteria.order);
+        let (o2generation, o2rounds) = find_with_rounds(o2criteria, data.width, &o2sorted)?;
+
+        let co2sorted = sort_for_scan(data, co2criteria.order);
+        let (co2scrubbing, co2rounds) = find_with_rounds(co2criteria, data.width, &co2sorted)?;
+
+        Ok(Explanation {
+            width: data.width,
+            len: data.len(),
+            bit_counts,
+            gamma: to_binary(&gamma, data.width),
+            epsilon: to_binary(&epsilon, data.width),
+            o2rounds,
+            o2generation: to_binary(o2generation, data.width),
+            co2rounds,
+            co2scrubbing: to_binary(co2scrubbing, data.width),
+        })
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>5} | {:>10} | {:>10} | gamma | epsilon",
+            "bit", "ones", "zeros"
+        )?;
+        writeln!(f, "{:-<6}+{:-<12}+{:-<12}+-------+--------", "", "", "")?;
+
+        for b in (0..self.width).rev() {
+            // Strings are written MSB first
+            let i = self.width - 1 - b;
+
+            writeln!(
+                f,
+                "{:>5} | {:>10} | {:>10} | {:>5} | {:>7}",
+                b,
+                self.bit_counts[b],
+                self.len - self.bit_counts[b],
+                &self.gamma[i..=i],
+                &self.epsilon[i..=i]
+            )?;
+        }
+
+        writeln!(f, "gamma = {}, epsilon = {}", self.gamma, self.epsilon)?;
+
+        let ratings = [
+            ("o2generation", &self.o2rounds, &self.o2generation),
+            ("co2scrubbing", &self.co2rounds, &self.co2scrubbing),
+        ];
+
+        for (name, rounds, value) in ratings {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "{:>5} | {:>5} | {:>10} | {:>10} | kept",
+                "round", "bit", "zeros", "ones"
+            )?;
+            writeln!(f, "{:-<6}+{:-<7}+{:-<12}+{:-<12}+------", "", "", "", "")?;
+
+            for (i, round) in rounds.iter().enumerate() {
+                let kept = match round.kept {
+                    Kept::Zeros => "zeros",
+                    Kept::Ones => "ones",
+                    Kept::All => "all",
+                };
+
+                writeln!(
+                    f,
+                    "{:>5} | {:>5} | {:>10} | {:>10} | {}",
+                    i + 1,
+                    round.bit,
+                    round.zeros,
+                    round.ones,
+                    kept
+                )?;
+            }
+
+            writeln!(f, "{} = {}", name, value)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -138,6 +543,44 @@ mod tests {
     use super::*;
     use indoc::indoc;
 
+    /// The original, straightforward implementation of [`bit_counts`].
+    fn bit_counts_naive(data: &Diagnostics) -> Vec<usize> {
+        let mut counts = vec![0usize; data.width];
+
+        for report in data.values() {
+            for (b, count) in counts.iter_mut().enumerate() {
+                if bit(report, b) {
+                    *count += 1;
+                }
+            }
+        }
+
+        counts
+    }
+
+    /// Generates `len` random values of `width` bits, using a xorshift generator.
+    fn generate(width: usize, len: usize, mut seed: u64) -> Diagnostics {
+        let mut data = Diagnostics::new(width);
+
+        for _ in 0..len * data.words {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            data.data.push(seed);
+        }
+
+        // Clear the unused high bits of the most significant words
+        if !width.is_multiple_of(64) {
+            let mask = (1 << (width % 64)) - 1;
+
+            for value in data.data.chunks_exact_mut(data.words) {
+                value[0] &= mask;
+            }
+        }
+
+        data
+    }
+
     const SAMPLE: &str = indoc! {"
         00100
         11110
@@ -155,8 +598,12 @@ mod tests {
 
     #[test]
     fn parses_the_input() {
+        let data = parse(SAMPLE).unwrap();
+        let values: Vec<_> = data.values().map(|v| to_u128(v).unwrap()).collect();
+
+        assert_eq!(data.width, 5);
         assert_eq!(
-            parse(SAMPLE).unwrap(),
+            values,
             vec![
                 0b00100, 0b11110, 0b10110, 0b10111, 0b10101, 0b01111, 0b00111, 0b11100, 0b10000,
                 0b11001, 0b00010, 0b01010,
@@ -167,21 +614,249 @@ mod tests {
     #[test]
     fn solves_the_first_example() {
         let data = parse(SAMPLE).unwrap();
-        assert_eq!(power_consumption(&data), 22 * 9);
+        assert_eq!(power_consumption(&data).unwrap(), 22 * 9);
     }
 
     #[test]
     fn solves_the_second_example() {
-        let mut data = parse(SAMPLE).unwrap();
-        assert_eq!(life_support_rating(&mut data), 23 * 10);
+        let data = parse(SAMPLE).unwrap();
+        assert_eq!(life_support_rating(&data).unwrap(), 23 * 10);
+    }
+
+    #[test]
+    fn rejects_inconsistent_widths() {
+        assert!(parse("").is_err());
+        assert!(parse("0101\n011\n").is_err());
+        assert!(parse("0101\n01x1\n").is_err());
+    }
+
+    #[test]
+    fn supports_wide_reports() {
+        // Repeating each sample value keeps which values are picked, and repeats the ratings too
+        let widen = |repeat| -> String {
+            SAMPLE
+                .lines()
+                .map(|line| line.repeat(repeat) + "\n")
+                .collect()
+        };
+        let expected = |a: &str, b: &str, repeat| {
+            let a = u128::from_str_radix(&a.repeat(repeat), 2).unwrap();
+            let b = u128::from_str_radix(&b.repeat(repeat), 2).unwrap();
+            a * b
+        };
+
+        for repeat in [7, 10, 12] {
+            let data = parse(&widen(repeat)).unwrap();
+
+            assert_eq!(data.width, 5 * repeat);
+            assert_eq!(
+                power_consumption(&data).unwrap(),
+                expected("10110", "01001", repeat)
+            );
+            assert_eq!(
+                life_support_rating(&data).unwrap(),
+                expected("10111", "01010", repeat)
+            );
+        }
+
+        // Even wider reports work, but their ratings no longer fit in 128 bits
+        let data = parse(&widen(20)).unwrap();
+
+        assert_eq!(data.width, 100);
+        assert_eq!(bit_counts(&data)[99], 7);
+        assert!(power_consumption(&data).is_err());
+        assert!(life_support_rating(&data).is_err());
+    }
+
+    #[test]
+    fn supports_other_criteria() {
+        // Straightforward (but slow) reference implementation of the bit criteria
+        fn naive(criteria: BitCriteria, data: &Diagnostics) -> Option<u128> {
+            let mut rest: Vec<_> = data.values().collect();
+            let bits: Vec<_> = match criteria.order {
+                ScanOrder::MsbFirst => (0..data.width).rev().collect(),
+                ScanOrder::LsbFirst => (0..data.width).collect(),
+            };
+
+            for b in bits {
+                let ones = rest.iter().filter(|x| bit(x, b)).count();
+                let zeros = rest.len() - ones;
+
+                if rest.len() == 1 || ones == 0 || zeros == 0 {
+                    continue;
+                }
+
+                let keep_ones = if ones == zeros {
+                    match criteria.tie {
+                        Tie::PreferOne => true,
+                        Tie::PreferZero => false,
+                        Tie::Fail => return None,
+                    }
+                } else {
+                    (ones > zeros) == (criteria.keep == Keep::WithMostCommonBits)
+                };
+
+                rest.retain(|x| bit(x, b) == keep_ones);
+            }
+
+            to_u128(rest[0])
+        }
+
+        for input in [SAMPLE, include_str!("../input.txt")] {
+            let data = parse(input).unwrap();
+
+            for keep in [Keep::WithMostCommonBits, Keep::WithLeastCommonBits] {
+                for tie in [Tie::PreferOne, Tie::PreferZero, Tie::Fail] {
+                    for order in [ScanOrder::MsbFirst, ScanOrder::LsbFirst] {
+                        let criteria = BitCriteria { keep, tie, order };
+                        let sorted = sort_for_scan(&data, order);
+                        let found = find_with(criteria, data.width, &sorted)
+                            .ok()
+                            .map(|value| to_u128(value).unwrap());
+
+                        assert_eq!(found, naive(criteria, &data), "{:?}", criteria);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reports_ambiguous_and_duplicate_values() {
+        let data = parse(SAMPLE).unwrap();
+        let sorted = sort_for_scan(&data, ScanOrder::LsbFirst);
+        let criteria = BitCriteria {
+            tie: Tie::Fail,
+            order: ScanOrder::LsbFirst,
+            ..BitCriteria::OXYGEN_GENERATOR
+        };
+
+        assert_eq!(
+            find_with(criteria, data.width, &sorted)
+                .unwrap_err()
+                .to_string(),
+            "ambiguous bit 2: 2 remaining values have it set and 2 do not"
+        );
+
+        let data = parse("0110\n0110\n1000\n").unwrap();
+        let sorted = sort_for_scan(&data, ScanOrder::MsbFirst);
+
+        assert!(find_with(BitCriteria::OXYGEN_GENERATOR, data.width, &sorted).is_err());
+        assert!(life_support_rating(&data).is_err());
+    }
+
+    #[test]
+    fn explains_the_ratings() {
+        let data = parse(SAMPLE).unwrap();
+        let explanation = Explanation::new(
+            &data,
+            BitCriteria::OXYGEN_GENERATOR,
+            BitCriteria::CO2_SCRUBBER,
+        )
+        .unwrap();
+
+        assert_eq!(explanation.bit_counts, vec![5, 7, 8, 5, 7]);
+        assert_eq!(explanation.gamma, "10110");
+        assert_eq!(explanation.epsilon, "01001");
+
+        let rounds = |rounds: &[Round]| -> Vec<_> {
+            rounds
+                .iter()
+                .map(|r| (r.bit, r.zeros, r.ones, r.kept))
+                .collect()
+        };
+
+        assert_eq!(
+            rounds(&explanation.o2rounds),
+            vec![
+                (4, 5, 7, Kept::Ones),
+                (3, 4, 3, Kept::Zeros),
+                (2, 1, 3, Kept::Ones),
+                (1, 1, 2, Kept::Ones),
+                (0, 1, 1, Kept::Ones),
+            ]
+        );
+        assert_eq!(explanation.o2generation, "10111");
+
+        assert_eq!(
+            rounds(&explanation.co2rounds),
+            vec![
+                (4, 5, 7, Kept::Zeros),
+                (3, 3, 2, Kept::Ones),
+                (2, 1, 1, Kept::Zeros)
+            ]
+        );
+        assert_eq!(explanation.co2scrubbing, "01010");
+
+        let table = explanation.to_string();
+        assert!(table.contains("    4 |          7 |          5 |     1 |       0\n"));
+        assert!(table.contains("    2 |     3 |          4 |          3 | zeros\n"));
+        assert!(table.ends_with("co2scrubbing = 01010\n"));
+    }
+
+    #[test]
+    fn transposes_bit_matrices() {
+        let mut m = [0u64; 64];
+        m[0] = 0b110;
+        m[5] = 1 << 63;
+
+        transpose(&mut m);
+
+        let mut expected = [0u64; 64];
+        expected[1] = 1;
+        expected[2] = 1;
+        expected[63] = 1 << 5;
+
+        assert_eq!(m, expected);
+    }
+
+    #[test]
+    fn counts_bits_like_the_naive_loop() {
+        for (width, len) in [(1, 1), (5, 63), (12, 1000), (48, 129), (64, 64), (100, 300)] {
+            let data = generate(width, len, 0x9e37_79b9_7f4a_7c15 + width as u64);
+            assert_eq!(
+                bit_counts(&data),
+                bit_counts_naive(&data),
+                "width {}",
+                width
+            );
+        }
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture bench`
+    #[test]
+    #[ignore]
+    fn bench_bit_counts() {
+        use std::time::Instant;
+
+        for width in [16, 48, 128] {
+            let data = generate(width, 10_000_000, 42);
+
+            let start = Instant::now();
+            let naive = bit_counts_naive(&data);
+            let naive_time = start.elapsed();
+
+            let start = Instant::now();
+            let transposed = bit_counts(&data);
+            let transposed_time = start.elapsed();
+
+            assert_eq!(transposed, naive);
+            println!(
+                "width {:>3}: naive {:>10.2?}, transposed {:>10.2?}, speedup {:.1}x",
+                width,
+                naive_time,
+                transposed_time,
+                naive_time.as_secs_f64() / transposed_time.as_secs_f64()
+            );
+        }
     }
 
     #[test]
     fn does_not_regress() {
         let input = include_str!("../input.txt");
-        let mut data = parse(input).unwrap();
+        let data = parse(input).unwrap();
 
-        assert_eq!(power_consumption(&data), 2724524);
-        assert_eq!(life_support_rating(&mut data), 2775870);
+        assert_eq!(power_consumption(&data).unwrap(), 2724524);
+        assert_eq!(life_support_rating(&data).unwrap(), 2775870);
     }
 }
@@ -1,93 +1,357 @@
-use eyre::{ensure, eyre, Context, Result};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use eyre::{bail, ensure, eyre, Context, Result};
 
 fn main() -> Result<()> {
+    let mut patterns = None;
+    let mut ranking = false;
+    let mut dump_after = None;
+    let mut generate_seed = None;
+    let mut generator = Generator::default();
+
+    for arg in std::env::args().skip(1) {
+        if let Some(value) = arg.strip_prefix("--patterns=") {
+            patterns = Some(
+                value
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<Vec<Pattern>>>()?,
+            );
+        } else if let Some(value) = arg.strip_prefix("--dump-after=") {
+            dump_after = Some(parse_arg(value, "draw count")?);
+        } else if arg == "--ranking" {
+            ranking = true;
+        } else if let Some(value) = arg.strip_prefix("--generate=") {
+            generate_seed = Some(parse_arg(value, "seed")?);
+        } else if let Some(value) = arg.strip_prefix("--boards=") {
+            generator.boards = parse_arg(value, "board count")?;
+        } else if let Some(value) = arg.strip_prefix("--size=") {
+            generator.rules.size = parse_arg(value, "board size")?;
+        } else if let Some(value) = arg.strip_prefix("--numbers=") {
+            let (low, high) = value
+                .split_once('-')
+                .ok_or_else(|| eyre!("expected a range like `0-99`, found `{}`", value))?;
+            generator.numbers = parse_arg(low, "number")?..=parse_arg(high, "number")?;
+        } else if arg == "--repeats" {
+            generator.unique = false;
+        } else if let Some(value) = arg.strip_prefix("--win-on=") {
+            generator.wins_on_draw = Some(parse_arg(value, "draw")?);
+        } else {
+            bail!("unknown argument `{}`", arg);
+        }
+    }
+
+    // Here `--patterns` sets the rules of the generated game instead of those of the input
+    if let Some(seed) = generate_seed {
+        if let Some(patterns) = patterns {
+            generator.rules.patterns = patterns;
+        }
+
+        print!("{}", generator.generate(seed)?);
+        return Ok(());
+    }
+
     println!("--- Day 4: Giant Squid ---");
 
     let input = include_str!("../input.txt");
-    let game = parse(input)?;
+    let mut game = parse(input)?;
+
+    if let Some(patterns) = patterns {
+        game.rules = Rules::new(game.rules.size, patterns)?;
+    }
+
+    let problems = validate(&game);
+
+    for problem in &problems {
+        match problem.severity() {
+            Severity::Warning => eprintln!("warning: {}", problem),
+            Severity::Error => eprintln!("error: {}", problem),
+        }
+    }
+
+    if let Some(problem) = problems
+        .into_iter()
+        .find(|p| p.severity() == Severity::Error)
+    {
+        return Err(problem.into());
+    }
+
+    println!("Score with winning board: {}", winning_board(game.clone())?);
+    println!("Score with losing board: {}", losing_board(game.clone()));
+
+    if ranking {
+        println!();
+        println!(
+            "{:>5} | {:>5} | {:>4} | {:>6} | {:>6}",
+            "rank", "board", "draw", "number", "score"
+        );
+
+        for (rank, finish) in simulate(game.clone()).iter().enumerate() {
+            println!(
+                "{:>5} | {:>5} | {:>4} | {:>6} | {:>6}",
+                rank + 1,
+                finish.board,
+                finish.draw,
+                finish.number,
+                finish.score
+            );
+        }
+    }
+
+    if let Some(draws) = dump_after {
+        let mut sim = Simulation::new(game);
 
-    println!("Score with winning board: {}", winning_board(game.clone()));
-    println!("Score with losing board: {}", losing_board(game));
+        for _ in 0..draws {
+            sim.step();
+        }
+
+        println!();
+        println!(
+            "After {} draws, {} boards have finished",
+            sim.draws(),
+            sim.finishes().len()
+        );
+
+        for (i, board) in sim.boards().iter().enumerate() {
+            println!();
+            println!("Board {}:", i);
+            print!("{}", board.dump(sim.rules().size));
+        }
+    }
 
     Ok(())
 }
 
+fn parse_arg<T>(value: &str, what: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value
+        .parse()
+        .wrap_err_with(|| format!("could not parse `{}` as {}", value, what))
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 struct Game {
     sequence: Vec<u8>,
     boards: Vec<Board>,
+    rules: Rules,
 }
 
+/// Ways in which the marked cells of a board can make it win.
 #[derive(Debug, PartialEq, Eq, Clone)]
-struct Board {
-    board: [u8; 25],
-    marked: [bool; 25],
-    done: Option<u32>,
+enum Pattern {
+    Rows,
+    Columns,
+    Diagonals,
+    FourCorners,
+    FullCard,
+    /// Custom set of cells, in row-major order.
+    Mask(Vec<bool>),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum BoardResult {
-    Pending,
-    Bingo(u32),
-    Done(u32),
+impl FromStr for Pattern {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Pattern> {
+        let pattern = match s {
+            "rows" => Pattern::Rows,
+            "columns" => Pattern::Columns,
+            "diagonals" => Pattern::Diagonals,
+            "corners" => Pattern::FourCorners,
+            "full" => Pattern::FullCard,
+            _ => {
+                let mask = s
+                    .strip_prefix("mask:")
+                    .ok_or_else(|| eyre!("unknown win pattern `{}`", s))?;
+
+                let mask = mask
+                    .chars()
+                    .map(|c| match c {
+                        '0' => Ok(false),
+                        '1' => Ok(true),
+                        _ => bail!("could not parse mask: {}", mask),
+                    })
+                    .collect::<Result<_>>()?;
+
+                Pattern::Mask(mask)
+            }
+        };
+
+        Ok(pattern)
+    }
 }
 
-impl Board {
-    pub fn new(board: [u8; 25]) -> Self {
-        Board {
-            board,
-            marked: [false; 25],
-            done: None,
+/// Writes the pattern the way [`Pattern::from_str`] reads it.
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Rows => write!(f, "rows"),
+            Pattern::Columns => write!(f, "columns"),
+            Pattern::Diagonals => write!(f, "diagonals"),
+            Pattern::FourCorners => write!(f, "corners"),
+            Pattern::FullCard => write!(f, "full"),
+            Pattern::Mask(mask) => {
+                let bits: String = mask.iter().map(|&x| if x { '1' } else { '0' }).collect();
+                write!(f, "mask:{}", bits)
+            }
         }
     }
+}
+
+/// The size of the (square) boards and how they can win.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Rules {
+    size: usize,
+    patterns: Vec<Pattern>,
+}
 
-    pub fn mark(&mut self, x: u8) -> BoardResult {
-        if let Some(score) = self.done {
-            return BoardResult::Done(score);
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            size: 5,
+            patterns: vec![Pattern::Rows, Pattern::Columns],
         }
+    }
+}
+
+impl Rules {
+    pub fn new(size: usize, patterns: Vec<Pattern>) -> Result<Self> {
+        ensure!(size > 0, "boards cannot be empty");
+        ensure!(!patterns.is_empty(), "no win patterns");
 
-        for (i, &n) in self.board.iter().enumerate() {
-            if n == x {
-                self.marked[i] = true;
+        for pattern in &patterns {
+            if let Pattern::Mask(mask) = pattern {
+                ensure!(
+                    mask.len() == size * size,
+                    "mask has {} cells but boards have {}",
+                    mask.len(),
+                    size * size
+                );
+                ensure!(mask.iter().any(|&x| x), "mask has no cells set");
             }
         }
 
-        if self.bingo() {
-            let score = self.score(x);
-            self.done = Some(score);
-            BoardResult::Bingo(score)
-        } else {
-            BoardResult::Pending
-        }
+        Ok(Rules { size, patterns })
     }
 
-    fn bingo(&self) -> bool {
-        for row in 0..5 {
-            let i = row * 5;
-            if self.marked[i..i + 5].iter().all(|&x| x) {
-                return true;
+    /// Lists the cells (as row-major indices) of every line that wins once fully marked.
+    fn lines(&self) -> Vec<Vec<usize>> {
+        let n = self.size;
+        let mut lines = vec![];
+
+        for pattern in &self.patterns {
+            match pattern {
+                Pattern::Rows => {
+                    lines.extend((0..n).map(|row| (0..n).map(|col| row * n + col).collect()))
+                }
+                Pattern::Columns => {
+                    lines.extend((0..n).map(|col| (0..n).map(|row| row * n + col).collect()))
+                }
+                Pattern::Diagonals => {
+                    lines.push((0..n).map(|i| i * n + i).collect());
+                    lines.push((0..n).map(|i| i * n + (n - 1 - i)).collect());
+                }
+                Pattern::FourCorners => lines.push(vec![0, n - 1, n * (n - 1), n * n - 1]),
+                Pattern::FullCard => lines.push((0..n * n).collect()),
+                Pattern::Mask(mask) => lines.push(
+                    mask.iter()
+                        .enumerate()
+                        .filter(|(_, &x)| x)
+                        .map(|(i, _)| i)
+                        .collect(),
+                ),
             }
         }
 
-        for col in 0..5 {
-            if (0..5).map(|row| self.marked[row * 5 + col]).all(|x| x) {
-                return true;
-            }
+        // Small boards can have overlapping patterns (e.g. the corners of a 2x2 board), and those
+        // need not be checked more than once
+        for line in lines.iter_mut() {
+            line.sort_unstable();
+            line.dedup();
         }
+        lines.sort();
+        lines.dedup();
 
-        false
+        lines
     }
+}
 
-    fn score(&self, winner: u8) -> u32 {
-        let mut acc: u32 = 0;
+/// Writes the game in the same format [`parse`] reads, with the win patterns after the boards
+/// unless they are the default ones.
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sequence: Vec<_> = self.sequence.iter().map(u8::to_string).collect();
+        writeln!(f, "{}", sequence.join(","))?;
 
-        for (&n, &marked) in self.board.iter().zip(self.marked.iter()) {
-            if !marked && n != winner {
-                acc += n as u32;
+        let width = self
+            .boards
+            .iter()
+            .flat_map(|board| &board.board)
+            .map(|n| n.to_string().len())
+            .max()
+            .unwrap_or(1);
+
+        for board in &self.boards {
+            writeln!(f)?;
+
+            for row in board.board.chunks(self.rules.size) {
+                let row: Vec<_> = row.iter().map(|n| format!("{:>width$}", n)).collect();
+                writeln!(f, "{}", row.join(" "))?;
             }
         }
 
-        acc * winner as u32
+        if self.rules.patterns != Rules::default().patterns {
+            let patterns: Vec<_> = self.rules.patterns.iter().map(Pattern::to_string).collect();
+            writeln!(f)?;
+            writeln!(f, "patterns: {}", patterns.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Board {
+    board: Vec<u8>,
+    marked: Vec<bool>,
+    done: Option<u64>,
+}
+
+impl Board {
+    pub fn new(board: Vec<u8>) -> Self {
+        Board {
+            marked: vec![false; board.len()],
+            board,
+            done: None,
+        }
+    }
+
+    /// Renders the board as a grid, with marked numbers in brackets.
+    fn dump(&self, size: usize) -> String {
+        let mut out = String::new();
+
+        for (row, marked) in self.board.chunks(size).zip(self.marked.chunks(size)) {
+            let cells: Vec<_> = row
+                .iter()
+                .zip(marked)
+                .map(|(&n, &marked)| {
+                    if marked {
+                        format!("[{:>3}]", n)
+                    } else {
+                        format!(" {:>3} ", n)
+                    }
+                })
+                .collect();
+
+            out.push_str(cells.join("").trim_end());
+            out.push('\n');
+        }
+
+        out
     }
 }
 
@@ -117,25 +381,37 @@ fn parse_blank_line<'a>(lines: &mut impl Iterator<Item = (&'a str, usize)>) -> R
         .ok_or_else(|| eyre!("no blank line"))?
 }
 
-fn parse_board<'a>(lines: &mut impl Iterator<Item = (&'a str, usize)>) -> Result<Board> {
-    let mut board = [0; 25];
-
-    for i in 0..5 {
-        let mut nums = lines
-            .next()
-            .map(|(line, lineno)| {
-                line.split_whitespace().map(move |x| {
+fn parse_row<'a>(lines: &mut impl Iterator<Item = (&'a str, usize)>) -> Result<Vec<u8>> {
+    lines
+        .next()
+        .map(|(line, lineno)| {
+            line.split_whitespace()
+                .map(|x| {
                     x.parse::<u8>()
                         .wrap_err_with(|| format!("{}: could not parse number: {}", lineno, x))
                 })
-            })
-            .ok_or_else(|| eyre!("incomplete table"))?;
+                .collect()
+        })
+        .ok_or_else(|| eyre!("incomplete table"))?
+}
 
-        for j in 0..5 {
-            board[i * 5 + j] = nums.next().ok_or_else(|| eyre!("incomplete line"))??;
-        }
+/// Parses a square board, whose size is inferred from its first row if not known yet.
+fn parse_board<'a>(
+    lines: &mut impl Iterator<Item = (&'a str, usize)>,
+    size: &mut Option<usize>,
+) -> Result<Board> {
+    let mut board = parse_row(lines)?;
+    let n = *size.get_or_insert(board.len());
 
-        ensure!(nums.next().is_none(), "trailing numbers");
+    ensure!(n > 0, "empty row");
+    ensure!(board.len() <= n, "trailing numbers");
+    ensure!(board.len() == n, "incomplete line");
+
+    for _ in 1..n {
+        let row = parse_row(lines)?;
+        ensure!(row.len() <= n, "trailing numbers");
+        ensure!(row.len() == n, "incomplete line");
+        board.extend(row);
     }
 
     Ok(Board::new(board))
@@ -146,40 +422,522 @@ fn parse(input: &str) -> Result<Game> {
 
     let sequence = parse_sequence(&mut lines)?;
     let mut boards = vec![];
+    let mut size = None;
+    let mut patterns = None;
 
     while lines.peek().is_some() {
-        let _ = parse_blank_line(&mut lines)?;
-        let board = parse_board(&mut lines)?;
+        parse_blank_line(&mut lines)?;
+
+        // Games with other win patterns than the default end with them
+        if let Some(&(line, lineno)) = lines.peek() {
+            if let Some(list) = line.strip_prefix("patterns: ") {
+                patterns = Some(
+                    list.split(',')
+                        .map(str::parse)
+                        .collect::<Result<Vec<Pattern>>>()
+                        .wrap_err_with(|| format!("{}: could not parse patterns", lineno))?,
+                );
+
+                lines.next();
+                ensure!(
+                    lines.peek().is_none(),
+                    "{}: patterns must come after the boards",
+                    lineno
+                );
+                break;
+            }
+        }
+
+        let board = parse_board(&mut lines, &mut size)?;
         boards.push(board);
     }
 
-    Ok(Game { sequence, boards })
+    let rules = Rules::new(
+        size.unwrap_or(5),
+        patterns.unwrap_or_else(|| Rules::default().patterns),
+    )?;
+
+    Ok(Game {
+        sequence,
+        boards,
+        rules,
+    })
+}
+
+/// How and when a board finished, with `draw` being an index into the sequence.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Finish {
+    board: usize,
+    draw: usize,
+    number: u8,
+    score: u64,
+}
+
+/// A game being played one draw at a time.
+///
+/// Instead of scanning every board on each draw, an index from numbers to the cells where they
+/// appear is kept, together with per-board counts of how many cells of each winning line are
+/// marked, and sums of the numbers still unmarked. This way a draw only touches the boards that
+/// have the number, and both bingos and scores are found in constant time.
+#[derive(Debug, Clone)]
+struct Simulation {
+    game: Game,
+    lines: Vec<Vec<usize>>,
+    draws: usize,
+    finishes: Vec<Finish>,
+    /// Cells (as `(board, cell)`) where each number appears, sorted by board.
+    index: Vec<Vec<(usize, usize)>>,
+    /// Winning lines that go through each cell.
+    cell_lines: Vec<Vec<usize>>,
+    /// Marked cells of each winning line of each board, in board-major order.
+    hits: Vec<u32>,
+    unmarked: Vec<u64>,
 }
 
-fn winning_board(mut game: Game) -> u32 {
-    for &x in game.sequence.iter() {
-        for game in game.boards.iter_mut() {
-            if let BoardResult::Bingo(score) = game.mark(x) {
-                return score;
+impl Simulation {
+    pub fn new(game: Game) -> Self {
+        let lines = game.rules.lines();
+        let cells = game.rules.size * game.rules.size;
+
+        let mut cell_lines = vec![vec![]; cells];
+        for (l, line) in lines.iter().enumerate() {
+            for &cell in line {
+                cell_lines[cell].push(l);
             }
         }
+
+        let mut index = vec![vec![]; u8::MAX as usize + 1];
+        for (b, board) in game.boards.iter().enumerate() {
+            for (cell, &n) in board.board.iter().enumerate() {
+                index[n as usize].push((b, cell));
+            }
+        }
+
+        let unmarked = game
+            .boards
+            .iter()
+            .map(|board| board.board.iter().map(|&n| n as u64).sum())
+            .collect();
+
+        Simulation {
+            hits: vec![0; game.boards.len() * lines.len()],
+            unmarked,
+            index,
+            cell_lines,
+            lines,
+            game,
+            draws: 0,
+            finishes: vec![],
+        }
+    }
+
+    /// Plays the next number in the sequence, returning the boards that finished with it (in
+    /// board order), or `None` if the sequence is exhausted.
+    pub fn step(&mut self) -> Option<&[Finish]> {
+        let draw = self.draws;
+        let &x = self.game.sequence.get(draw)?;
+        let before = self.finishes.len();
+
+        let mut cells = &self.index[x as usize][..];
+
+        while let Some(&(b, _)) = cells.first() {
+            // Mark all cells with `x` in this board before checking if it won
+            let n = cells.iter().take_while(|&&(other, _)| other == b).count();
+            let (group, rest) = cells.split_at(n);
+            cells = rest;
+
+            let board = &mut self.game.boards[b];
+            if board.done.is_some() {
+                continue;
+            }
+
+            let hits = &mut self.hits[b * self.lines.len()..(b + 1) * self.lines.len()];
+            let mut bingo = false;
+
+            for &(_, cell) in group {
+                if board.marked[cell] {
+                    continue;
+                }
+
+                board.marked[cell] = true;
+                self.unmarked[b] -= x as u64;
+
+                for &l in &self.cell_lines[cell] {
+                    hits[l] += 1;
+                    bingo |= hits[l] as usize == self.lines[l].len();
+                }
+            }
+
+            if bingo {
+                let score = self.unmarked[b] * x as u64;
+                board.done = Some(score);
+
+                self.finishes.push(Finish {
+                    board: b,
+                    draw,
+                    number: x,
+                    score,
+                });
+            }
+        }
+
+        self.draws += 1;
+        Some(&self.finishes[before..])
+    }
+
+    /// Number of draws played so far.
+    pub fn draws(&self) -> usize {
+        self.draws
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.game.rules
+    }
+
+    pub fn boards(&self) -> &[Board] {
+        &self.game.boards
+    }
+
+    /// Boards that have finished so far, ranked by finishing order.
+    pub fn finishes(&self) -> &[Finish] {
+        &self.finishes
+    }
+}
+
+/// Plays the whole game, ranking the boards that finished by their finishing order.
+fn simulate(game: Game) -> Vec<Finish> {
+    let mut sim = Simulation::new(game);
+
+    while sim.step().is_some() {}
+
+    sim.finishes
+}
+
+fn winning_board(game: Game) -> Result<u64, Problem> {
+    let mut sim = Simulation::new(game);
+
+    while let Some(finished) = sim.step() {
+        if let Some(first) = finished.first() {
+            return Ok(first.score);
+        }
     }
 
-    unreachable!()
+    Err(Problem::NoWinner)
+}
+
+fn losing_board(game: Game) -> u64 {
+    simulate(game).last().map_or(0, |finish| finish.score)
 }
 
-fn losing_board(mut game: Game) -> u32 {
-    let mut last_score = 0;
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Severity {
+    Warning,
+    Error,
+}
 
-    for &x in game.sequence.iter() {
-        for game in game.boards.iter_mut() {
-            if let BoardResult::Bingo(score) = game.mark(x) {
-                last_score = score;
+/// Something wrong with a game.
+///
+/// Lines are those where the problem would be found in the input format [`parse`] reads.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Problem {
+    DuplicateNumber {
+        board: usize,
+        number: u8,
+        line: usize,
+    },
+    UnusedNumber {
+        number: u8,
+        draw: usize,
+    },
+    NeverWins {
+        board: usize,
+        line: usize,
+    },
+    NoWinner,
+}
+
+impl Problem {
+    fn severity(&self) -> Severity {
+        match self {
+            Problem::NoWinner => Severity::Error,
+            // Repeated numbers are all marked at once, which the simulation handles fine
+            Problem::DuplicateNumber { .. }
+            | Problem::UnusedNumber { .. }
+            | Problem::NeverWins { .. } => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::DuplicateNumber {
+                board,
+                number,
+                line,
+            } => write!(f, "{}: number {} repeated in board {}", line, number, board),
+            Problem::UnusedNumber { number, draw } => {
+                write!(f, "1: number {} (draw {}) is on no board", number, draw)
             }
+            Problem::NeverWins { board, line } => write!(
+                f,
+                "{}: board {} never wins before the sequence is exhausted",
+                line, board
+            ),
+            Problem::NoWinner => write!(f, "no board wins before the sequence is exhausted"),
         }
     }
+}
+
+impl std::error::Error for Problem {}
+
+/// Checks a game for duplicate numbers within boards, drawn numbers that are on no board, and
+/// boards (or whole games) without a winner.
+fn validate(game: &Game) -> Vec<Problem> {
+    let size = game.rules.size;
+    let mut problems = vec![];
+
+    // The sequence and a blank line come first, then each board and a blank line
+    let first_line = |board: usize| 3 + board * (size + 1);
+
+    let mut on_boards = [false; u8::MAX as usize + 1];
+
+    for (b, board) in game.boards.iter().enumerate() {
+        let mut seen = [false; u8::MAX as usize + 1];
+
+        for (cell, &n) in board.board.iter().enumerate() {
+            if seen[n as usize] {
+                problems.push(Problem::DuplicateNumber {
+                    board: b,
+                    number: n,
+                    line: first_line(b) + cell / size,
+                });
+            }
+
+            seen[n as usize] = true;
+            on_boards[n as usize] = true;
+        }
+    }
+
+    for (draw, &n) in game.sequence.iter().enumerate() {
+        if !on_boards[n as usize] {
+            problems.push(Problem::UnusedNumber { number: n, draw });
+        }
+    }
+
+    let finishes = simulate(game.clone());
+
+    if finishes.is_empty() {
+        // Every board never winning is implied here
+        problems.push(Problem::NoWinner);
+        return problems;
+    }
+
+    let mut finished = vec![false; game.boards.len()];
+    for finish in &finishes {
+        finished[finish.board] = true;
+    }
 
-    last_score
+    for (b, _) in finished.iter().enumerate().filter(|(_, &f)| !f) {
+        problems.push(Problem::NeverWins {
+            board: b,
+            line: first_line(b),
+        });
+    }
+
+    problems
+}
+
+/// Small xorshift64* generator, so that generated games only depend on their seed.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Scramble the seed with splitmix64, which also keeps the state from being zero
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng((z ^ (z >> 31)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns a number in `0..n`; the modulo bias is irrelevant for the small `n` used here.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// Options for generating games.
+///
+/// The sequence draws every number in `numbers` exactly once, in random order. When
+/// `wins_on_draw` is set, the game is arranged so that the first board wins exactly on that draw
+/// (an index into the sequence).
+#[derive(Debug, Clone)]
+struct Generator {
+    boards: usize,
+    rules: Rules,
+    numbers: RangeInclusive<u8>,
+    unique: bool,
+    wins_on_draw: Option<usize>,
+}
+
+impl Default for Generator {
+    fn default() -> Self {
+        Generator {
+            boards: 100,
+            rules: Rules::default(),
+            numbers: 0..=99,
+            unique: true,
+            wins_on_draw: None,
+        }
+    }
+}
+
+// Give up on reaching `wins_on_draw` after this many attempts
+const MAX_ATTEMPTS: usize = 1000;
+
+impl Generator {
+    pub fn generate(&self, seed: u64) -> Result<Game> {
+        let rules = Rules::new(self.rules.size, self.rules.patterns.clone())?;
+        let numbers: Vec<u8> = self.numbers.clone().collect();
+        let cells = rules.size * rules.size;
+
+        ensure!(!numbers.is_empty(), "empty number range");
+        ensure!(
+            !self.unique || numbers.len() >= cells,
+            "cannot fill boards of {} cells with {} unique numbers",
+            cells,
+            numbers.len()
+        );
+
+        let mut rng = Rng::new(seed);
+
+        for _ in 0..MAX_ATTEMPTS {
+            let boards = (0..self.boards)
+                .map(|_| Board::new(self.generate_board(&mut rng, &numbers, cells)))
+                .collect();
+
+            let mut game = Game {
+                sequence: numbers.clone(),
+                boards,
+                rules: rules.clone(),
+            };
+
+            match self.wins_on_draw {
+                None => {
+                    rng.shuffle(&mut game.sequence);
+                    return Ok(game);
+                }
+                Some(k) => {
+                    ensure!(k < numbers.len(), "there are only {} draws", numbers.len());
+
+                    if let Some(sequence) = arrange_sequence(&mut rng, &game, k) {
+                        game.sequence = sequence;
+                        return Ok(game);
+                    }
+                }
+            }
+        }
+
+        bail!("could not generate a game where the first board wins on the requested draw")
+    }
+
+    fn generate_board(&self, rng: &mut Rng, numbers: &[u8], cells: usize) -> Vec<u8> {
+        if self.unique {
+            let mut pool = numbers.to_vec();
+            rng.shuffle(&mut pool);
+            pool.truncate(cells);
+            pool
+        } else {
+            (0..cells)
+                .map(|_| numbers[rng.below(numbers.len())])
+                .collect()
+        }
+    }
+}
+
+/// Tries to order all numbers in the game's sequence so that no board wins before draw `k`, and
+/// some random line of a random board is completed exactly on it.
+fn arrange_sequence(rng: &mut Rng, game: &Game, k: usize) -> Option<Vec<u8>> {
+    let lines = game.rules.lines();
+
+    let board = &game.boards.get(rng.below(game.boards.len().max(1)))?.board;
+    let line = &lines[rng.below(lines.len())];
+
+    let mut target: Vec<u8> = line.iter().map(|&cell| board[cell]).collect();
+    target.sort_unstable();
+    target.dedup();
+    rng.shuffle(&mut target);
+
+    if target.len() > k + 1 {
+        return None;
+    }
+
+    let mut drawn = [false; u8::MAX as usize + 1];
+    let wins = |drawn: &[bool]| {
+        game.boards.iter().any(|board| {
+            lines
+                .iter()
+                .any(|line| line.iter().all(|&cell| drawn[board.board[cell] as usize]))
+        })
+    };
+
+    // All but one of the numbers in the target line are drawn before `k`, and the last one on it
+    let last = target.pop()?;
+    for &n in &target {
+        drawn[n as usize] = true;
+    }
+
+    let mut rest: Vec<u8> = game
+        .sequence
+        .iter()
+        .copied()
+        .filter(|&n| n != last && !drawn[n as usize])
+        .collect();
+    rng.shuffle(&mut rest);
+
+    // Fill the remaining draws before `k` with numbers that do not make any board win; since
+    // marking more numbers never undoes a win, it is enough to check the set of drawn numbers
+    let mut before = target;
+    let mut after = vec![];
+
+    for n in rest {
+        if before.len() < k {
+            drawn[n as usize] = true;
+
+            if !wins(&drawn) {
+                before.push(n);
+                continue;
+            }
+
+            drawn[n as usize] = false;
+        }
+
+        after.push(n);
+    }
+
+    if before.len() < k || wins(&drawn) {
+        return None;
+    }
+
+    rng.shuffle(&mut before);
+    before.push(last);
+    before.extend(after);
+
+    Some(before)
 }
 
 #[cfg(test)]
@@ -187,6 +945,50 @@ mod tests {
     use super::*;
     use indoc::indoc;
 
+    /// Plays the game by scanning every board on every draw, like it was originally done.
+    fn simulate_naive(mut game: Game) -> Vec<Finish> {
+        let lines = game.rules.lines();
+        let mut finishes = vec![];
+
+        for (draw, &x) in game.sequence.iter().enumerate() {
+            for (i, board) in game.boards.iter_mut().enumerate() {
+                if board.done.is_some() {
+                    continue;
+                }
+
+                for (n, marked) in board.board.iter().zip(board.marked.iter_mut()) {
+                    if *n == x {
+                        *marked = true;
+                    }
+                }
+
+                if lines
+                    .iter()
+                    .any(|line| line.iter().all(|&c| board.marked[c]))
+                {
+                    let unmarked = board
+                        .board
+                        .iter()
+                        .zip(&board.marked)
+                        .filter(|(&n, &marked)| !marked && n != x)
+                        .map(|(&n, _)| n as u64)
+                        .sum::<u64>();
+
+                    let score = unmarked * x as u64;
+                    board.done = Some(score);
+                    finishes.push(Finish {
+                        board: i,
+                        draw,
+                        number: x,
+                        score,
+                    });
+                }
+            }
+        }
+
+        finishes
+    }
+
     const SAMPLE: &str = indoc! {"
         7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1
 
@@ -223,7 +1025,7 @@ mod tests {
                 ],
                 boards: vec![
                     Board::new(
-                        [
+                        vec![
                             22, 13, 17, 11,  0,
                              8,  2, 23,  4, 24,
                             21,  9, 14, 16,  7,
@@ -232,7 +1034,7 @@ mod tests {
                         ]
                     ),
                     Board::new(
-                        [
+                        vec![
                              3, 15,  0,  2, 22,
                              9, 18, 13, 17,  5,
                             19,  8,  7, 25, 23,
@@ -241,7 +1043,7 @@ mod tests {
                         ]
                     ),
                     Board::new(
-                        [
+                        vec![
                             14, 21, 17, 24,  4,
                             10, 16, 15,  9, 19,
                             18,  8, 23, 26, 20,
@@ -250,6 +1052,7 @@ mod tests {
                         ]
                     )
                 ],
+                rules: Rules::default(),
             }
         );
     }
@@ -257,7 +1060,7 @@ mod tests {
     #[test]
     fn solves_the_first_example() {
         let game = parse(SAMPLE).unwrap();
-        assert_eq!(winning_board(game), 188 * 24);
+        assert_eq!(winning_board(game).unwrap(), 188 * 24);
     }
 
     #[test]
@@ -271,15 +1074,343 @@ mod tests {
         let input = include_str!("../input.txt");
         let game = parse(input).unwrap();
 
-        assert_eq!(winning_board(game.clone()), 2496);
+        assert_eq!(winning_board(game.clone()).unwrap(), 2496);
         assert_eq!(losing_board(game), 25925);
     }
 
+    #[test]
+    fn lists_winning_lines() {
+        let rules = Rules::new(
+            3,
+            vec![
+                Pattern::Rows,
+                Pattern::Diagonals,
+                Pattern::FourCorners,
+                "mask:010111010".parse().unwrap(),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules.lines(),
+            vec![
+                vec![0, 1, 2],
+                vec![0, 2, 6, 8],
+                vec![0, 4, 8],
+                vec![1, 3, 4, 5, 7],
+                vec![2, 4, 6],
+                vec![3, 4, 5],
+                vec![6, 7, 8],
+            ]
+        );
+
+        assert!(Rules::new(3, vec!["mask:0101".parse().unwrap()]).is_err());
+        assert!(Rules::new(3, vec![]).is_err());
+        assert!("stars".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn plays_other_variants() {
+        let mut game = parse(SAMPLE).unwrap();
+
+        // The third board is the first to complete a diagonal: 4, 9, 23, 11 and then 2
+        game.rules = Rules::new(5, vec![Pattern::Diagonals]).unwrap();
+        assert_eq!(winning_board(game.clone()).unwrap(), 247 * 2);
+
+        game.rules = Rules::new(5, vec![Pattern::FullCard]).unwrap();
+        assert_eq!(winning_board(game.clone()).unwrap(), 0);
+    }
+
+    #[test]
+    fn parses_other_board_sizes() {
+        let input = indoc! {"
+            1,2,3,4
+
+            1 2
+            3 4
+
+            4 3
+            9 8
+        "};
+
+        let game = parse(input).unwrap();
+
+        assert_eq!(game.rules.size, 2);
+        assert_eq!(game.boards[1], Board::new(vec![4, 3, 9, 8]));
+        assert_eq!(winning_board(game.clone()).unwrap(), (3 + 4) * 2);
+        assert_eq!(losing_board(game), (9 + 8) * 4);
+
+        assert!(parse("1,2\n\n1 2\n3 4\n\n1 2 3\n4 5 6\n7 8 9\n").is_err());
+        assert!(parse("1,2\n\n1 2\n3\n").is_err());
+    }
+
+    #[test]
+    fn ranks_all_boards() {
+        let game = parse(SAMPLE).unwrap();
+
+        assert_eq!(
+            simulate(game),
+            vec![
+                Finish {
+                    board: 2,
+                    draw: 11,
+                    number: 24,
+                    score: 188 * 24
+                },
+                Finish {
+                    board: 0,
+                    draw: 13,
+                    number: 16,
+                    score: 137 * 16
+                },
+                Finish {
+                    board: 1,
+                    draw: 14,
+                    number: 13,
+                    score: 148 * 13
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn replays_draw_by_draw() {
+        let game = parse(SAMPLE).unwrap();
+        let mut sim = Simulation::new(game);
+
+        for _ in 0..11 {
+            assert_eq!(sim.step(), Some(&[][..]));
+        }
+
+        assert_eq!(sim.step().map(|f| f.len()), Some(1));
+        assert_eq!(sim.draws(), 12);
+        assert_eq!(sim.finishes()[0].board, 2);
+
+        assert_eq!(
+            sim.boards()[2].dump(5),
+            indoc! {"
+                [ 14][ 21][ 17][ 24][  4]
+                  10   16   15 [  9]  19
+                  18    8 [ 23]  26   20
+                  22 [ 11]  13    6 [  5]
+                [  2][  0]  12    3 [  7]
+            "}
+        );
+
+        while sim.step().is_some() {}
+        assert_eq!(sim.draws(), 27);
+        assert_eq!(sim.step(), None);
+    }
+
+    #[test]
+    fn indexed_marking_matches_scanning() {
+        use Pattern::*;
+
+        let repeated = indoc! {"
+            5,5,1,2,3,4,1,9,6,7,8
+
+            1 1 2
+            3 4 5
+            5 6 7
+
+            9 8 7
+            6 5 4
+            3 2 1
+
+            2 2 2
+            2 2 2
+            2 2 2
+        "};
+
+        let variants = [
+            vec![Rows, Columns],
+            vec![Diagonals],
+            vec![FourCorners, Rows],
+            vec![FullCard],
+        ];
+
+        for input in [SAMPLE, include_str!("../input.txt"), repeated] {
+            let mut game = parse(input).unwrap();
+
+            for patterns in variants.iter().cloned() {
+                game.rules = Rules::new(game.rules.size, patterns).unwrap();
+                assert_eq!(simulate(game.clone()), simulate_naive(game.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn validates_games() {
+        assert_eq!(validate(&parse(SAMPLE).unwrap()), vec![]);
+
+        let input = indoc! {"
+            1,2,9,42,4
+
+            1 2 9
+            3 4 5
+            5 6 7
+
+            9 8 7
+            6 5 4
+            3 9 1
+        "};
+
+        let game = parse(input).unwrap();
+        let problems = validate(&game);
+
+        assert_eq!(
+            problems,
+            vec![
+                Problem::DuplicateNumber {
+                    board: 0,
+                    number: 5,
+                    line: 5
+                },
+                Problem::DuplicateNumber {
+                    board: 1,
+                    number: 9,
+                    line: 9
+                },
+                Problem::UnusedNumber {
+                    number: 42,
+                    draw: 3
+                },
+                Problem::NeverWins { board: 1, line: 7 },
+            ]
+        );
+        assert_eq!(problems[0].to_string(), "5: number 5 repeated in board 0");
+        assert_eq!(problems[0].severity(), Severity::Warning);
+        assert_eq!(problems[3].severity(), Severity::Warning);
+
+        // Games generated with repeated numbers are only warned about
+        let generator = Generator {
+            boards: 10,
+            numbers: 1..=20,
+            unique: false,
+            wins_on_draw: Some(8),
+            ..Generator::default()
+        };
+        let game = generator.generate(3).unwrap();
+        let problems = validate(&game);
+
+        assert!(problems
+            .iter()
+            .any(|p| matches!(p, Problem::DuplicateNumber { .. })));
+        assert!(problems.iter().all(|p| p.severity() == Severity::Warning));
+    }
+
+    #[test]
+    fn reports_games_without_winners() {
+        let mut game = parse(SAMPLE).unwrap();
+        game.sequence.truncate(10);
+
+        assert_eq!(winning_board(game.clone()), Err(Problem::NoWinner));
+        assert_eq!(validate(&game).last(), Some(&Problem::NoWinner));
+    }
+
+    #[test]
+    fn generated_games_round_trip() {
+        let generator = Generator {
+            boards: 10,
+            ..Generator::default()
+        };
+
+        let game = generator.generate(42).unwrap();
+        let text = game.to_string();
+
+        assert_eq!(parse(&text).unwrap(), game);
+        assert_eq!(generator.generate(42).unwrap(), game);
+        assert_ne!(generator.generate(43).unwrap(), game);
+
+        let mut sequence = game.sequence.clone();
+        sequence.sort_unstable();
+        assert_eq!(sequence, (0..=99).collect::<Vec<u8>>());
+
+        assert!(validate(&game)
+            .iter()
+            .all(|p| p.severity() == Severity::Warning));
+
+        // The sample round trips too, except for the alignment of its numbers
+        let sample = parse(SAMPLE).unwrap();
+        assert_eq!(parse(&sample.to_string()).unwrap(), sample);
+    }
+
+    #[test]
+    fn generates_games_won_on_a_given_draw() {
+        for (k, unique, size) in [(4, true, 5), (12, true, 5), (30, false, 4), (70, true, 3)] {
+            let generator = Generator {
+                boards: 20,
+                rules: Rules {
+                    size,
+                    ..Rules::default()
+                },
+                unique,
+                wins_on_draw: Some(k),
+                ..Generator::default()
+            };
+
+            for seed in 0..5 {
+                let game = generator.generate(seed).unwrap();
+
+                assert_eq!(simulate(game.clone())[0].draw, k);
+                assert_eq!(parse(&game.to_string()).unwrap(), game);
+            }
+        }
+
+        let impossible = Generator {
+            wins_on_draw: Some(3),
+            ..Generator::default()
+        };
+        assert!(impossible.generate(0).is_err());
+    }
+
+    #[test]
+    fn generated_games_round_trip_with_other_patterns() {
+        let mut mask = vec![false; 25];
+        mask[0] = true;
+        mask[12] = true;
+
+        for patterns in [
+            vec![Pattern::Diagonals],
+            vec![Pattern::Columns, Pattern::FourCorners, Pattern::Mask(mask)],
+        ] {
+            let generator = Generator {
+                rules: Rules::new(5, patterns).unwrap(),
+                wins_on_draw: Some(10),
+                ..Generator::default()
+            };
+
+            let game = generator.generate(7).unwrap();
+            let back = parse(&game.to_string()).unwrap();
+
+            assert_eq!(back, game);
+            assert_eq!(simulate(back)[0].draw, 10);
+        }
+
+        assert!(parse("1,2\n\npatterns: diagonals\n\n1\n").is_err());
+        assert!(parse("1,2\n\npatterns: stripes\n").is_err());
+    }
+
+    #[test]
+    fn rejects_impossible_generator_options() {
+        let too_few_numbers = Generator {
+            numbers: 1..=20,
+            ..Generator::default()
+        };
+        assert!(too_few_numbers.generate(0).is_err());
+
+        let repeats = Generator {
+            unique: false,
+            ..too_few_numbers
+        };
+        assert!(repeats.generate(0).is_ok());
+    }
+
     #[test]
     fn does_not_overflow_on_degenerate_case() {
         #[rustfmt::skip]
         let boards = vec![
-            Board::new([
+            Board::new(vec![
                 79, 80, 81, 82, 83,
                 84, 85, 86, 87, 88,
                 89, 90, 91, 92, 93,
@@ -289,12 +1420,34 @@ mod tests {
         ];
         let sequence = vec![0, 1, 2, 3, 99];
 
-        let game = Game { sequence, boards };
+        let game = Game {
+            sequence,
+            boards,
+            rules: Rules::default(),
+        };
 
         assert_eq!(
-            winning_board(game.clone()) as usize,
+            winning_board(game.clone()).unwrap() as usize,
             (79..99).sum::<usize>() * 99
         );
         assert_eq!(losing_board(game) as usize, (79..99).sum::<usize>() * 99);
     }
+
+    #[test]
+    fn does_not_overflow_on_large_boards() {
+        let size = 300;
+        let mut board = vec![255; size * size];
+        board[..size].fill(200);
+
+        let game = Game {
+            sequence: vec![200],
+            boards: vec![Board::new(board)],
+            rules: Rules::new(size, vec![Pattern::Rows]).unwrap(),
+        };
+
+        let score = 255 * (size * size - size) as u64 * 200;
+        assert!(score > u32::MAX as u64);
+        assert_eq!(winning_board(game.clone()).unwrap(), score);
+        assert_eq!(simulate_naive(game)[0].score, score);
+    }
 }
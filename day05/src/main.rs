@@ -1,21 +1,133 @@
-use std::fmt::Display;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::str::FromStr;
 
-use eyre::{eyre, Result, WrapErr};
+use eyre::{bail, ensure, eyre, Result, WrapErr};
 use regex::Regex;
 
 fn main() -> Result<()> {
-    println!("--- Day 5: Hydrothermal Venture ---");
+    let mut raster = None;
+    let mut analytic = false;
+    let mut render = None;
+    let mut histogram = false;
+    let mut hottest = None;
+    let mut cells = vec![];
+
+    for arg in std::env::args().skip(1) {
+        if let Some(value) = arg.strip_prefix("--raster=") {
+            raster = Some(value.parse::<Raster>()?);
+        } else if let Some(value) = arg.strip_prefix("--render=") {
+            render = Some(value.to_string());
+        } else if arg == "--analytic" {
+            analytic = true;
+        } else if arg == "--histogram" {
+            histogram = true;
+        } else if let Some(value) = arg.strip_prefix("--hottest=") {
+            hottest = Some(
+                value
+                    .parse::<usize>()
+                    .wrap_err_with(|| format!("could not parse cell count `{}`", value))?,
+            );
+        } else if let Some(value) = arg.strip_prefix("--cell=") {
+            let (x, y) = value
+                .split_once(',')
+                .ok_or_else(|| eyre!("expected a cell like `3,4`, found `{}`", value))?;
+            let coord = |c: &str| {
+                c.parse()
+                    .wrap_err_with(|| format!("could not parse coordinate `{}`", c))
+            };
+
+            cells.push(Point {
+                x: coord(x)?,
+                y: coord(y)?,
+            });
+        } else {
+            bail!("unknown argument `{}`", arg);
+        }
+    }
 
     let input = include_str!("../input.txt");
     let lines = parse(input)?;
 
-    println!("Straight line overlaps: {}", straight_overlaps(&lines));
-    println!("Any line overlaps: {}", more_complete_overlaps(&lines));
+    if let Some(format) = render {
+        let stdout = std::io::stdout();
+        let map = build_map(&lines, raster.unwrap_or(Raster::Lattice));
+
+        match format.as_str() {
+            "text" => write_diagram(stdout.lock(), &map)?,
+            "ppm" => write_ppm(stdout.lock(), &map)?,
+            "svg" => write_svg(stdout.lock(), &lines)?,
+            _ => bail!("unknown render format `{}`", format),
+        }
+
+        return Ok(());
+    }
+
+    println!("--- Day 5: Hydrothermal Venture ---");
+
+    if analytic {
+        let straight: Vec<_> = lines
+            .iter()
+            .copied()
+            .filter(|line| line.horizontal() || line.vertical())
+            .collect();
+
+        println!("Straight line overlaps: {}", analytic_overlaps(&straight));
+        println!("Any line overlaps: {}", analytic_overlaps(&lines));
+    } else {
+        println!("Straight line overlaps: {}", straight_overlaps(&lines));
+        println!("Any line overlaps: {}", more_complete_overlaps(&lines));
+    }
+
+    if let Some(raster) = raster {
+        println!(
+            "Any line overlaps ({:?}): {}",
+            raster,
+            overlaps_with(&lines, raster)
+        );
+    }
+
+    if histogram || hottest.is_some() || !cells.is_empty() {
+        let field = VentField::new(&lines, raster.unwrap_or(Raster::Lattice));
+
+        let saturated = field.saturated();
+        if !saturated.is_empty() {
+            eprintln!(
+                "warning: {} cells are covered by {} lines or more, their counts are lower bounds",
+                saturated.len(),
+                u16::MAX
+            );
+        }
+
+        if histogram {
+            println!("Cells by number of lines:");
+            for (count, cells) in field.histogram() {
+                println!("  {:>5}: {}", count, cells);
+            }
+        }
+
+        if let Some(k) = hottest {
+            println!("Hottest cells:");
+            for (p, count) in field.hottest(k) {
+                println!("  {},{}: {}", p.x, p.y, count);
+            }
+        }
+
+        for p in cells {
+            println!("Lines through {},{}:", p.x, p.y);
+            for line in field.lines_through(p) {
+                println!(
+                    "  {},{} -> {},{}",
+                    line.p1.x, line.p1.y, line.p2.x, line.p2.y
+                );
+            }
+        }
+    }
 
     Ok(())
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Point {
     x: i32,
     y: i32,
@@ -36,13 +148,145 @@ impl Line {
         self.p1.x == self.p2.x
     }
 
-    fn at_45_degrees(&self) -> bool {
-        (self.p2.x - self.p1.x).abs() == (self.p2.y - self.p1.y).abs()
+    // In i64, as the difference of two i32 can overflow
+    fn delta(&self) -> (i64, i64) {
+        (
+            self.p2.x as i64 - self.p1.x as i64,
+            self.p2.y as i64 - self.p1.y as i64,
+        )
+    }
+
+    /// Whether the line covers cell `p`.
+    fn covers(&self, p: Point, raster: Raster) -> bool {
+        let bounds = Bounds::of(std::slice::from_ref(self)).expect("a line has points");
+        if !bounds.contains(p) {
+            return false;
+        }
+
+        match raster {
+            // Every lattice point between the ends is covered, as the steps are primitive
+            Raster::Lattice => {
+                let (dx, dy) = self.delta();
+                let (wx, wy) = (p.x as i64 - self.p1.x as i64, p.y as i64 - self.p1.y as i64);
+
+                dx as i128 * wy as i128 == dy as i128 * wx as i128
+            }
+            Raster::Bresenham => self.points(raster).any(|q| q == p),
+        }
+    }
+
+    /// Cells covered by the line, from `p1` to `p2`.
+    fn points(&self, raster: Raster) -> Box<dyn Iterator<Item = Point>> {
+        let (dx, dy) = self.delta();
+        let (x, y) = (self.p1.x as i64, self.p1.y as i64);
+
+        match raster {
+            Raster::Lattice => {
+                let steps = gcd(dx.abs(), dy.abs());
+                let (sx, sy) = if steps == 0 {
+                    (0, 0)
+                } else {
+                    (dx / steps, dy / steps)
+                };
+
+                Box::new((0..=steps).map(move |i| Point {
+                    x: (x + i * sx) as i32,
+                    y: (y + i * sy) as i32,
+                }))
+            }
+            Raster::Bresenham => Box::new(Bresenham {
+                x,
+                y,
+                end: self.p2,
+                dx: dx.abs(),
+                dy: -dy.abs(),
+                sx: dx.signum(),
+                sy: dy.signum(),
+                err: dx.abs() - dy.abs(),
+                done: false,
+            }),
+        }
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// How lines are turned into cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Raster {
+    /// Only the cells the line passes exactly through. Horizontal, vertical and 45° lines cover
+    /// every cell along them, other lines only the points where they cross the integer grid.
+    Lattice,
+    /// A connected run of cells closest to the line, one per step along its major axis.
+    Bresenham,
+}
+
+impl FromStr for Raster {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lattice" => Ok(Raster::Lattice),
+            "bresenham" => Ok(Raster::Bresenham),
+            _ => bail!("unknown raster mode `{}`", s),
+        }
+    }
+}
+
+// All octants version of Bresenham's algorithm, with the error term in i64 so that it cannot
+// overflow for any pair of i32 points
+struct Bresenham {
+    x: i64,
+    y: i64,
+    end: Point,
+    dx: i64,
+    dy: i64,
+    sx: i64,
+    sy: i64,
+    err: i64,
+    done: bool,
+}
+
+impl Iterator for Bresenham {
+    type Item = Point;
+
+    fn next(&mut self) -> Option<Point> {
+        if self.done {
+            return None;
+        }
+
+        let p = Point {
+            x: self.x as i32,
+            y: self.y as i32,
+        };
+
+        if p == self.end {
+            self.done = true;
+            return Some(p);
+        }
+
+        let e2 = 2 * self.err;
+        if e2 >= self.dy {
+            self.err += self.dy;
+            self.x += self.sx;
+        }
+        if e2 <= self.dx {
+            self.err += self.dx;
+            self.y += self.sy;
+        }
+
+        Some(p)
     }
 }
 
 fn parse(input: &str) -> Result<Vec<Line>> {
-    let re = Regex::new(r"^(\d+),(\d+) -> (\d+),(\d+)$").expect("could not build regex");
+    let re = Regex::new(r"^(-?\d+),(-?\d+) -> (-?\d+),(-?\d+)$").expect("could not build regex");
 
     input
         .lines()
@@ -75,80 +319,568 @@ fn parse(input: &str) -> Result<Vec<Line>> {
         .collect()
 }
 
-// Internally uses column-major order to match how the diagram is supposed to look.
+/// Inclusive bounding box of a set of points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bounds {
+    min: Point,
+    max: Point,
+}
+
+impl Bounds {
+    fn of(lines: &[Line]) -> Option<Bounds> {
+        Bounds::of_points(lines.iter().flat_map(|line| [line.p1, line.p2]))
+    }
+
+    fn of_points(mut points: impl Iterator<Item = Point>) -> Option<Bounds> {
+        let first = points.next()?;
+
+        Some(points.fold(
+            Bounds {
+                min: first,
+                max: first,
+            },
+            |bounds, p| Bounds {
+                min: Point {
+                    x: bounds.min.x.min(p.x),
+                    y: bounds.min.y.min(p.y),
+                },
+                max: Point {
+                    x: bounds.max.x.max(p.x),
+                    y: bounds.max.y.max(p.y),
+                },
+            },
+        ))
+    }
+
+    // Computed in i64 so that the full i32 range does not overflow
+    fn width(&self) -> u64 {
+        (self.max.x as i64 - self.min.x as i64 + 1) as u64
+    }
+
+    fn height(&self) -> u64 {
+        (self.max.y as i64 - self.min.y as i64 + 1) as u64
+    }
+
+    fn area(&self) -> u64 {
+        self.width().saturating_mul(self.height())
+    }
+
+    fn contains(&self, p: Point) -> bool {
+        (self.min.x..=self.max.x).contains(&p.x) && (self.min.y..=self.max.y).contains(&p.y)
+    }
+
+    /// Row-major index of `p` into a grid covering these bounds.
+    fn index(&self, p: Point) -> Option<usize> {
+        if !self.contains(p) {
+            return None;
+        }
+
+        let x = (p.x as i64 - self.min.x as i64) as u64;
+        let y = (p.y as i64 - self.min.y as i64) as u64;
+
+        Some((y * self.width() + x) as usize)
+    }
+
+    /// Inverse of [`Bounds::index`].
+    fn point(&self, i: usize) -> Point {
+        let width = self.width() as usize;
+
+        Point {
+            x: (self.min.x as i64 + (i % width) as i64) as i32,
+            y: (self.min.y as i64 + (i / width) as i64) as i32,
+        }
+    }
+}
+
+// Bounding boxes larger than this are stored sparsely (32 MiB worth of counters)
+const MAX_DENSE_CELLS: u64 = 1 << 24;
+
+/// Number of lines covering each cell.
+///
+/// Cells are stored in a grid over the lines' bounding box when it is small enough, and in a
+/// hash map of the covered cells otherwise.
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct Map(Vec<Vec<u16>>);
+enum Map {
+    // Row-major order, to match how the diagram is supposed to look
+    Dense { bounds: Bounds, cells: Vec<u16> },
+    Sparse(HashMap<Point, u16>),
+}
 
 impl Map {
-    fn new() -> Map {
-        Map(vec![vec![0; 1024]; 1024])
+    fn new(lines: &[Line]) -> Map {
+        match Bounds::of(lines) {
+            Some(bounds) if bounds.area() <= MAX_DENSE_CELLS => Map::Dense {
+                bounds,
+                cells: vec![0; bounds.area() as usize],
+            },
+            _ => Map::sparse(),
+        }
     }
 
-    fn add_straight_line(&mut self, line: &Line) {
-        debug_assert!(line.horizontal() || line.vertical());
+    fn sparse() -> Map {
+        Map::Sparse(HashMap::new())
+    }
 
-        for x in line.p1.x.min(line.p2.x)..=line.p1.x.max(line.p2.x) {
-            for y in line.p1.y.min(line.p2.y)..=line.p1.y.max(line.p2.y) {
-                let (x, y) = (x as usize, y as usize);
-                self.0[y][x] = self.0[y][x].saturating_add(1);
-            }
+    fn mark(&mut self, p: Point) {
+        let cell = match self {
+            Map::Dense { bounds, cells } => match bounds.index(p) {
+                Some(i) => &mut cells[i],
+                None => {
+                    // Lines outside of the bounds the map was built for: fall back to sparse
+                    self.make_sparse();
+                    return self.mark(p);
+                }
+            },
+            Map::Sparse(cells) => cells.entry(p).or_insert(0),
+        };
+
+        *cell = cell.saturating_add(1);
+    }
+
+    fn make_sparse(&mut self) {
+        if let Map::Dense { .. } = self {
+            *self = Map::Sparse(self.cells().collect());
         }
     }
 
-    fn add_line(&mut self, line: &Line) {
-        if line.vertical() {
-            self.add_straight_line(line);
-        } else {
-            debug_assert!(line.horizontal() || line.at_45_degrees());
+    fn add_line(&mut self, line: &Line, raster: Raster) {
+        for p in line.points(raster) {
+            self.mark(p);
+        }
+    }
 
-            let m = (line.p2.y - line.p1.y) / (line.p2.x - line.p1.x);
-            let b = line.p1.y - m * line.p1.x;
+    fn counts(&self) -> Box<dyn Iterator<Item = u16> + '_> {
+        match self {
+            Map::Dense { cells, .. } => Box::new(cells.iter().copied()),
+            Map::Sparse(cells) => Box::new(cells.values().copied()),
+        }
+    }
 
-            for x in line.p1.x.min(line.p2.x)..=line.p1.x.max(line.p2.x) {
-                let y = m * x + b;
+    fn overlaps(&self) -> usize {
+        self.counts().filter(|&count| count >= 2).count()
+    }
 
-                let (x, y) = (x as usize, y as usize);
-                self.0[y][x] = self.0[y][x].saturating_add(1);
-            }
+    fn get(&self, p: Point) -> u16 {
+        match self {
+            Map::Dense { bounds, cells } => bounds.index(p).map_or(0, |i| cells[i]),
+            Map::Sparse(cells) => cells.get(&p).copied().unwrap_or(0),
         }
     }
 
-    fn overlaps(&self) -> usize {
-        let mut count = 0;
+    /// Cells covered by at least one line, with their counts.
+    fn cells(&self) -> Box<dyn Iterator<Item = (Point, u16)> + '_> {
+        match self {
+            Map::Dense { bounds, cells } => Box::new(
+                cells
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &count)| count > 0)
+                    .map(|(i, &count)| (bounds.point(i), count)),
+            ),
+            Map::Sparse(cells) => Box::new(
+                cells
+                    .iter()
+                    .filter(|(_, &count)| count > 0)
+                    .map(|(&p, &count)| (p, count)),
+            ),
+        }
+    }
 
-        for col in self.0.iter() {
-            for &x in col {
-                if x >= 2 {
-                    count += 1;
-                }
-            }
+    /// Bounds of the cells covered by at least one line.
+    fn occupied(&self) -> Option<Bounds> {
+        Bounds::of_points(self.cells().map(|(p, _)| p))
+    }
+
+    /// Occupied bounds, checked to be small enough to be drawn cell by cell.
+    fn drawable(&self) -> Result<Option<Bounds>> {
+        let bounds = self.occupied();
+
+        if let Some(bounds) = bounds {
+            ensure!(
+                bounds.area() <= MAX_DENSE_CELLS,
+                "{}x{} cells are too many to draw",
+                bounds.width(),
+                bounds.height()
+            );
         }
 
-        count
+        Ok(bounds)
     }
 }
 
-fn straight_overlaps(lines: &[Line]) -> usize {
-    let mut map = Map::new();
+/// Writes the diagram as in the puzzle: `.` for cells without any line, the number of lines
+/// otherwise, and `#` for cells with more than 9 lines. Empty rows and columns around the lines
+/// are left out.
+fn write_diagram(mut out: impl Write, map: &Map) -> Result<()> {
+    let bounds = match map.drawable()? {
+        Some(bounds) => bounds,
+        None => return Ok(()),
+    };
 
-    for line in lines {
-        if line.horizontal() || line.vertical() {
-            map.add_straight_line(line);
+    for y in bounds.min.y..=bounds.max.y {
+        let row: String = (bounds.min.x..=bounds.max.x)
+            .map(|x| match map.get(Point { x, y }) {
+                0 => '.',
+                count @ 1..=9 => char::from_digit(count as u32, 10).expect("not a digit"),
+                _ => '#',
+            })
+            .collect();
+
+        writeln!(out, "{}", row)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a binary PPM image of the occupied region, one pixel per cell, from black for cells
+/// without any line to white for the most covered ones.
+fn write_ppm(mut out: impl Write, map: &Map) -> Result<()> {
+    let bounds = map.drawable()?.unwrap_or(Bounds {
+        min: Point { x: 0, y: 0 },
+        max: Point { x: 0, y: 0 },
+    });
+    let max = map.counts().max().unwrap_or(0).max(1) as u32;
+
+    writeln!(out, "P6\n{} {}\n255", bounds.width(), bounds.height())?;
+
+    let mut pixels = Vec::with_capacity(bounds.area() as usize * 3);
+    for y in bounds.min.y..=bounds.max.y {
+        for x in bounds.min.x..=bounds.max.x {
+            let gray = (map.get(Point { x, y }) as u32 * 255 / max) as u8;
+            pixels.extend([gray; 3]);
         }
     }
 
-    map.overlaps()
+    out.write_all(&pixels)?;
+
+    Ok(())
+}
+
+/// Writes an SVG drawing of the lines themselves, translucent so that overlaps stand out.
+fn write_svg(mut out: impl Write, lines: &[Line]) -> Result<()> {
+    let bounds = Bounds::of(lines).unwrap_or(Bounds {
+        min: Point { x: 0, y: 0 },
+        max: Point { x: 0, y: 0 },
+    });
+
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        bounds.min.x as f64 - 0.5,
+        bounds.min.y as f64 - 0.5,
+        bounds.width(),
+        bounds.height()
+    )?;
+    writeln!(
+        out,
+        r#"  <g stroke="black" stroke-opacity="0.4" stroke-width="0.3" stroke-linecap="round">"#
+    )?;
+
+    for line in lines {
+        writeln!(
+            out,
+            r#"    <line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+            line.p1.x, line.p1.y, line.p2.x, line.p2.y
+        )?;
+    }
+
+    writeln!(out, "  </g>")?;
+    writeln!(out, "</svg>")?;
+
+    Ok(())
+}
+
+fn straight_overlaps(lines: &[Line]) -> usize {
+    let straight: Vec<_> = lines
+        .iter()
+        .copied()
+        .filter(|line| line.horizontal() || line.vertical())
+        .collect();
+
+    overlaps_with(&straight, Raster::Lattice)
 }
 
 fn more_complete_overlaps(lines: &[Line]) -> usize {
-    let mut map = Map::new();
+    overlaps_with(lines, Raster::Lattice)
+}
+
+/// Counts the cells covered by at least two lines, of any angle.
+fn overlaps_with(lines: &[Line], raster: Raster) -> usize {
+    build_map(lines, raster).overlaps()
+}
+
+fn build_map(lines: &[Line], raster: Raster) -> Map {
+    let mut map = Map::new(lines);
 
     for line in lines {
-        debug_assert!(line.horizontal() || line.vertical() || line.at_45_degrees());
-        map.add_line(line);
+        map.add_line(line, raster);
     }
 
-    map.overlaps()
+    map
+}
+
+/// Lines along with the map they make, to answer questions about particular cells.
+///
+/// Counts saturate at `u16::MAX`; [`VentField::saturated`] lists the cells where that happened.
+struct VentField<'a> {
+    lines: &'a [Line],
+    raster: Raster,
+    map: Map,
+}
+
+impl<'a> VentField<'a> {
+    fn new(lines: &'a [Line], raster: Raster) -> Self {
+        VentField {
+            lines,
+            raster,
+            map: build_map(lines, raster),
+        }
+    }
+
+    /// Number of cells for each number of lines covering them, for cells with any line.
+    fn histogram(&self) -> BTreeMap<u16, usize> {
+        let mut histogram = BTreeMap::new();
+
+        for count in self.map.counts().filter(|&count| count > 0) {
+            *histogram.entry(count).or_insert(0) += 1;
+        }
+
+        histogram
+    }
+
+    /// The `k` cells with the most lines, ties broken top to bottom then left to right.
+    fn hottest(&self, k: usize) -> Vec<(Point, u16)> {
+        let mut cells: Vec<_> = self.map.cells().collect();
+        cells.sort_unstable_by_key(|&(p, count)| (std::cmp::Reverse(count), p.y, p.x));
+        cells.truncate(k);
+
+        cells
+    }
+
+    fn lines_through(&self, p: Point) -> Vec<&'a Line> {
+        self.lines
+            .iter()
+            .filter(|line| line.covers(p, self.raster))
+            .collect()
+    }
+
+    fn saturated(&self) -> Vec<Point> {
+        let mut cells: Vec<_> = self
+            .map
+            .cells()
+            .filter(|&(_, count)| count == u16::MAX)
+            .map(|(p, _)| p)
+            .collect();
+        cells.sort_unstable_by_key(|p| (p.y, p.x));
+
+        cells
+    }
+}
+
+/// Counts the cells covered by at least two lines like `overlaps_with(lines, Raster::Lattice)`,
+/// but without rasterizing anything, so that it works for lines of any length.
+///
+/// Lines lying on the same infinite line are merged as intervals along it, and the crossing
+/// points of all other pairs are found with a sweep over x.
+fn analytic_overlaps(lines: &[Line]) -> u64 {
+    let mut segments: Vec<Segment> = lines.iter().map(Segment::new).collect();
+
+    // Collinear overlaps, as intervals of lattice points along each infinite line
+    let mut groups: HashMap<LineKey, Vec<(i128, i128)>> = HashMap::new();
+    for segment in &segments {
+        groups
+            .entry(segment.key)
+            .or_default()
+            .push((segment.t, segment.t + segment.steps as i128));
+    }
+
+    let shared: HashMap<LineKey, Vec<(i128, i128)>> = groups
+        .into_iter()
+        .map(|(key, intervals)| (key, covered_twice(intervals)))
+        .filter(|(_, intervals)| !intervals.is_empty())
+        .collect();
+
+    let mut count: u64 = shared
+        .values()
+        .flatten()
+        .map(|(start, end)| (end - start + 1) as u64)
+        .sum();
+
+    // Crossings between lines of different directions, skipping those already counted above
+    segments.sort_by_key(|segment| segment.min.x);
+
+    let mut crossings = HashMap::new();
+    for (i, a) in segments.iter().enumerate() {
+        for b in segments[i + 1..].iter().take_while(|b| b.min.x <= a.max.x) {
+            if let Some(p) = a.crossing(b) {
+                crossings
+                    .entry(p)
+                    .or_insert_with(Vec::new)
+                    .extend([a.key, b.key]);
+            }
+        }
+    }
+
+    for (p, mut keys) in crossings {
+        keys.sort_unstable_by_key(|key| (key.dx, key.dy, key.c));
+        keys.dedup();
+
+        let shared_by = keys
+            .iter()
+            .filter(|key| {
+                shared.get(key).is_some_and(|intervals| {
+                    let t = key.position(p);
+                    intervals
+                        .iter()
+                        .any(|&(start, end)| (start..=end).contains(&t))
+                })
+            })
+            .count();
+
+        // Cells where collinear overlaps of different lines cross were counted once per line
+        match shared_by {
+            0 => count += 1,
+            n => count -= n as u64 - 1,
+        }
+    }
+
+    count
+}
+
+/// Identifies an infinite line through lattice points by its primitive direction (pointing
+/// right, or up when vertical) and its cross product with any of its points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct LineKey {
+    dx: i64,
+    dy: i64,
+    c: i128,
+}
+
+impl LineKey {
+    /// Index of a lattice point of the line along it. Consecutive points have consecutive
+    /// indices, since their dot products with the direction all share the same remainder.
+    fn position(&self, p: Point) -> i128 {
+        let dot = self.dx as i128 * p.x as i128 + self.dy as i128 * p.y as i128;
+        let norm = self.dx as i128 * self.dx as i128 + self.dy as i128 * self.dy as i128;
+
+        dot.div_euclid(norm)
+    }
+}
+
+/// A line as the lattice points `start + i * (dx, dy)` for `i` in `0..=steps`.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    key: LineKey,
+    start: Point,
+    steps: i64,
+    t: i128,
+    min: Point,
+    max: Point,
+}
+
+impl Segment {
+    fn new(line: &Line) -> Segment {
+        let (dx, dy) = line.delta();
+        let steps = gcd(dx.abs(), dy.abs());
+
+        // Single points get an arbitrary direction, which makes them collinear with the
+        // horizontal lines through them
+        let (mut dx, mut dy, mut start) = if steps == 0 {
+            (1, 0, line.p1)
+        } else {
+            (dx / steps, dy / steps, line.p1)
+        };
+
+        if dx < 0 || (dx == 0 && dy < 0) {
+            (dx, dy, start) = (-dx, -dy, line.p2);
+        }
+
+        let key = LineKey {
+            dx,
+            dy,
+            c: dx as i128 * start.y as i128 - dy as i128 * start.x as i128,
+        };
+
+        Segment {
+            key,
+            start,
+            steps,
+            t: key.position(start),
+            min: Point {
+                x: line.p1.x.min(line.p2.x),
+                y: line.p1.y.min(line.p2.y),
+            },
+            max: Point {
+                x: line.p1.x.max(line.p2.x),
+                y: line.p1.y.max(line.p2.y),
+            },
+        }
+    }
+
+    /// The lattice point where two lines of different directions cross, if any.
+    fn crossing(&self, other: &Segment) -> Option<Point> {
+        if self.min.y > other.max.y || other.min.y > self.max.y {
+            return None;
+        }
+
+        let cross = |ax: i128, ay: i128, bx: i128, by: i128| ax * by - ay * bx;
+
+        let (ax, ay) = (self.key.dx as i128, self.key.dy as i128);
+        let (bx, by) = (other.key.dx as i128, other.key.dy as i128);
+        let wx = other.start.x as i128 - self.start.x as i128;
+        let wy = other.start.y as i128 - self.start.y as i128;
+
+        let denominator = cross(ax, ay, bx, by);
+        if denominator == 0 {
+            return None;
+        }
+
+        // Solves start + s * a = other.start + u * b, where both s and u must be whole steps
+        let s = cross(wx, wy, bx, by);
+        let u = cross(wx, wy, ax, ay);
+
+        if s % denominator != 0 || u % denominator != 0 {
+            return None;
+        }
+
+        let (s, u) = (s / denominator, u / denominator);
+        if !(0..=self.steps as i128).contains(&s) || !(0..=other.steps as i128).contains(&u) {
+            return None;
+        }
+
+        Some(Point {
+            x: (self.start.x as i128 + s * ax) as i32,
+            y: (self.start.y as i128 + s * ay) as i32,
+        })
+    }
+}
+
+/// Merges inclusive intervals into the disjoint ones covered by at least two of them.
+fn covered_twice(intervals: Vec<(i128, i128)>) -> Vec<(i128, i128)> {
+    let mut events: Vec<(i128, i32)> = intervals
+        .into_iter()
+        .flat_map(|(start, end)| [(start, 1), (end + 1, -1)])
+        .collect();
+    events.sort_unstable();
+
+    let mut shared: Vec<(i128, i128)> = vec![];
+    let mut depth = 0;
+
+    for (i, &(position, change)) in events.iter().enumerate() {
+        depth += change;
+
+        let next = events.get(i + 1).map(|&(next, _)| next);
+        if depth >= 2 && next != Some(position) {
+            let end = next.expect("intervals are closed") - 1;
+
+            match shared.last_mut() {
+                Some(last) if last.1 + 1 == position => last.1 = end,
+                _ => shared.push((position, end)),
+            }
+        }
+    }
+
+    shared
 }
 
 #[cfg(test)]
@@ -232,6 +964,318 @@ mod tests {
         assert_eq!(more_complete_overlaps(&lines), 12);
     }
 
+    #[test]
+    fn sparse_map_matches_dense_map() {
+        let lines = parse(include_str!("../input.txt")).unwrap();
+
+        let mut dense = Map::new(&lines);
+        let mut sparse = Map::sparse();
+        assert!(matches!(dense, Map::Dense { .. }));
+
+        for line in &lines {
+            dense.add_line(line, Raster::Lattice);
+            sparse.add_line(line, Raster::Lattice);
+        }
+
+        assert_eq!(sparse.overlaps(), dense.overlaps());
+
+        dense.make_sparse();
+        assert_eq!(dense, sparse);
+    }
+
+    #[test]
+    fn supports_the_full_coordinate_range() {
+        let lines = parse(indoc! {"
+            -2147483648,-5 -> -2147483648,5
+            -2147483648,0 -> -2147483640,0
+            2147483647,2147483647 -> 2147483640,2147483640
+            2147483641,2147483641 -> 2147483641,2147483647
+            2147483647,2147483641 -> 2147483641,2147483647
+        "})
+        .unwrap();
+
+        assert!(matches!(Map::new(&lines), Map::Sparse(_)));
+        assert_eq!(straight_overlaps(&lines), 1);
+        assert_eq!(more_complete_overlaps(&lines), 4);
+
+        assert!(parse("2147483648,0 -> 0,0").is_err());
+    }
+
+    #[test]
+    fn grows_beyond_its_bounds() {
+        let lines = parse(SAMPLE).unwrap();
+        let mut map = Map::new(&lines[..1]);
+
+        for line in &lines {
+            map.add_line(line, Raster::Bresenham);
+        }
+
+        assert!(matches!(map, Map::Sparse(_)));
+        assert_eq!(map.overlaps(), 12);
+    }
+
+    #[test]
+    fn rasterizes_lines_at_any_angle() {
+        let line = |x1, y1, x2, y2| Line {
+            p1: Point { x: x1, y: y1 },
+            p2: Point { x: x2, y: y2 },
+        };
+        let points =
+            |line: Line, raster| line.points(raster).map(|p| (p.x, p.y)).collect::<Vec<_>>();
+
+        assert_eq!(
+            points(line(0, 0, 6, -4), Raster::Lattice),
+            vec![(0, 0), (3, -2), (6, -4)]
+        );
+        assert_eq!(
+            points(line(0, 0, 4, 2), Raster::Bresenham),
+            vec![(0, 0), (1, 1), (2, 1), (3, 2), (4, 2)]
+        );
+        assert_eq!(points(line(3, 3, 3, 3), Raster::Lattice), vec![(3, 3)]);
+        assert_eq!(points(line(3, 3, 3, 3), Raster::Bresenham), vec![(3, 3)]);
+
+        // Both agree on the lines of the puzzle
+        for line in parse(SAMPLE).unwrap() {
+            assert_eq!(
+                points(line, Raster::Lattice),
+                points(line, Raster::Bresenham)
+            );
+        }
+
+        // Bresenham covers a connected run of cells from end to end
+        let steep = line(-7, 20, 2, -11);
+        let covered = points(steep, Raster::Bresenham);
+        assert_eq!(covered.len(), 32);
+        assert_eq!(covered.first(), Some(&(-7, 20)));
+        assert_eq!(covered.last(), Some(&(2, -11)));
+        assert!(covered
+            .windows(2)
+            .all(|w| (w[0].0 - w[1].0).abs() <= 1 && w[1].1 == w[0].1 - 1));
+
+        let lines = vec![line(0, 0, 6, 4), line(0, 4, 6, 0), line(3, 0, 3, 4)];
+        assert_eq!(overlaps_with(&lines, Raster::Lattice), 1);
+        assert_eq!(more_complete_overlaps(&lines), 1);
+        assert_eq!(overlaps_with(&lines, Raster::Bresenham), 1);
+
+        assert!("lattice".parse::<Raster>().is_ok());
+        assert!("wu".parse::<Raster>().is_err());
+    }
+
+    #[test]
+    fn renders_sample_diagram() {
+        let lines = parse(SAMPLE).unwrap();
+
+        let mut diagram = vec![];
+        write_diagram(&mut diagram, &build_map(&lines, Raster::Lattice)).unwrap();
+
+        assert_eq!(
+            String::from_utf8(diagram).unwrap(),
+            indoc! {"
+                1.1....11.
+                .111...2..
+                ..2.1.111.
+                ...1.2.2..
+                .112313211
+                ...1.2....
+                ..1...1...
+                .1.....1..
+                1.......1.
+                222111....
+            "}
+        );
+
+        // Cropped to the lines, wherever they are
+        let far = parse("-100,2000 -> -98,2000\n-99,2000 -> -99,2001\n").unwrap();
+        let mut diagram = vec![];
+        write_diagram(&mut diagram, &build_map(&far, Raster::Lattice)).unwrap();
+
+        assert_eq!(String::from_utf8(diagram).unwrap(), "121\n.1.\n");
+    }
+
+    #[test]
+    fn renders_heatmap_and_svg() {
+        let lines = parse(SAMPLE).unwrap();
+
+        let mut ppm = vec![];
+        write_ppm(&mut ppm, &build_map(&lines, Raster::Lattice)).unwrap();
+
+        let header = b"P6\n10 10\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+
+        let pixels = &ppm[header.len()..];
+        assert_eq!(pixels.len(), 10 * 10 * 3);
+        assert_eq!(&pixels[..6], &[85, 85, 85, 0, 0, 0]);
+        // The only cells with 3 lines are at 4,4 and 6,4
+        assert_eq!(pixels.iter().filter(|&&p| p == 255).count(), 6);
+        assert_eq!(&pixels[(4 * 10 + 4) * 3..][..3], &[255, 255, 255]);
+        assert_eq!(&pixels[(4 * 10 + 6) * 3..][..3], &[255, 255, 255]);
+
+        let mut svg = vec![];
+        write_svg(&mut svg, &lines).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+
+        assert!(svg
+            .starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="-0.5 -0.5 10 10">"#));
+        assert!(svg.contains(r#"<line x1="8" y1="0" x2="0" y2="8"/>"#));
+        assert_eq!(svg.matches("<line ").count(), lines.len());
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn refuses_to_draw_huge_maps() {
+        let lines = parse("0,0 -> 100000,100000\n").unwrap();
+        let map = build_map(&lines, Raster::Lattice);
+
+        assert!(write_diagram(vec![], &map).is_err());
+        assert!(write_ppm(vec![], &map).is_err());
+        assert!(write_svg(vec![], &lines).is_ok());
+    }
+
+    #[test]
+    fn answers_cell_queries() {
+        let lines = parse(SAMPLE).unwrap();
+        let field = VentField::new(&lines, Raster::Lattice);
+
+        assert_eq!(
+            field.histogram(),
+            BTreeMap::from([(1, 27), (2, 10), (3, 2)])
+        );
+        assert_eq!(
+            field.hottest(3),
+            vec![
+                (Point { x: 4, y: 4 }, 3),
+                (Point { x: 6, y: 4 }, 3),
+                (Point { x: 7, y: 1 }, 2)
+            ]
+        );
+        assert_eq!(field.hottest(100).len(), 39);
+        assert_eq!(
+            field.lines_through(Point { x: 4, y: 4 }),
+            vec![&lines[1], &lines[2], &lines[8]]
+        );
+        assert!(field.lines_through(Point { x: 9, y: 9 }).is_empty());
+        assert!(field.saturated().is_empty());
+
+        // Both rasters agree on which lines go through each cell they cover
+        let steep = parse("0,0 -> 3,7\n").unwrap();
+        for raster in [Raster::Lattice, Raster::Bresenham] {
+            let field = VentField::new(&steep, raster);
+
+            for (p, _) in field.map.cells() {
+                assert_eq!(field.lines_through(p), vec![&steep[0]]);
+            }
+            assert!(field.lines_through(Point { x: 1, y: 1 }).is_empty());
+        }
+    }
+
+    #[test]
+    fn reports_saturated_cells() {
+        let mut lines = vec![
+            Line {
+                p1: Point { x: 1, y: 1 },
+                p2: Point { x: 2, y: 1 },
+            };
+            u16::MAX as usize + 10
+        ];
+        lines.push(Line {
+            p1: Point { x: 2, y: 1 },
+            p2: Point { x: 3, y: 1 },
+        });
+
+        let field = VentField::new(&lines, Raster::Lattice);
+
+        assert_eq!(
+            field.saturated(),
+            vec![Point { x: 1, y: 1 }, Point { x: 2, y: 1 }]
+        );
+        assert_eq!(field.hottest(1), vec![(Point { x: 1, y: 1 }, u16::MAX)]);
+        assert_eq!(field.histogram(), BTreeMap::from([(1, 1), (u16::MAX, 2)]));
+    }
+
+    #[test]
+    fn counts_overlaps_analytically() {
+        let sample = parse(SAMPLE).unwrap();
+        let input = parse(include_str!("../input.txt")).unwrap();
+
+        for lines in [sample, input] {
+            let straight: Vec<_> = lines
+                .iter()
+                .copied()
+                .filter(|line| line.horizontal() || line.vertical())
+                .collect();
+
+            assert_eq!(
+                analytic_overlaps(&straight),
+                straight_overlaps(&lines) as u64
+            );
+            assert_eq!(
+                analytic_overlaps(&lines),
+                more_complete_overlaps(&lines) as u64
+            );
+        }
+
+        // Lines at any angle, including single points and lines on top of each other
+        let mut state = 0x2545_f491_u64;
+        let mut coordinate = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 9) as i32 - 4
+        };
+
+        for _ in 0..50 {
+            let lines: Vec<_> = (0..12)
+                .map(|_| Line {
+                    p1: Point {
+                        x: coordinate(),
+                        y: coordinate(),
+                    },
+                    p2: Point {
+                        x: coordinate(),
+                        y: coordinate(),
+                    },
+                })
+                .collect();
+
+            assert_eq!(
+                analytic_overlaps(&lines),
+                overlaps_with(&lines, Raster::Lattice) as u64,
+                "{:?}",
+                lines
+            );
+        }
+    }
+
+    #[test]
+    fn counts_overlaps_of_huge_lines() {
+        let lines = parse(indoc! {"
+            -2000000000,7 -> 2000000000,7
+            0,7 -> 2100000000,7
+            5,-2000000000 -> 5,2000000000
+            -2000000000,-2000000000 -> 2000000000,2000000000
+            -2000000000,-1999999999 -> 2000000000,2000000001
+        "})
+        .unwrap();
+
+        // The two horizontal lines share 2000000001 cells, which also contain where the vertical
+        // line and the diagonals cross them; the vertical line crosses both diagonals elsewhere
+        assert_eq!(analytic_overlaps(&lines), 2_000_000_001 + 2);
+
+        // Collinear overlaps crossing each other share a single cell
+        let crossed = parse(indoc! {"
+            0,0 -> 10,10
+            2,2 -> 8,8
+            0,10 -> 10,0
+            2,8 -> 8,2
+        "})
+        .unwrap();
+        assert_eq!(analytic_overlaps(&crossed), 7 + 7 - 1);
+        assert_eq!(
+            analytic_overlaps(&crossed),
+            overlaps_with(&crossed, Raster::Lattice) as u64
+        );
+    }
+
     #[test]
     fn does_not_regress() {
         let input = include_str!("../input.txt");
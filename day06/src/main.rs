@@ -1,6 +1,28 @@
-use eyre::{Result, WrapErr};
+use std::fmt;
+use std::str::FromStr;
+
+use eyre::{bail, ensure, eyre, Result, WrapErr};
+use num_bigint::BigUint;
 
 fn main() -> Result<()> {
+    let mut life_cycle = LifeCycle::LANTERNFISH;
+    let mut days = None;
+    let mut modulus = None;
+
+    for arg in std::env::args().skip(1) {
+        let (name, value) = arg.split_once('=').unwrap_or((&arg, ""));
+
+        match name {
+            "--reset" => life_cycle.reset = parse_arg(value, "timer")?,
+            "--newborn" => life_cycle.newborn = parse_arg(value, "timer")?,
+            "--offspring" => life_cycle.offspring = parse_arg(value, "offspring count")?,
+            "--death-age" => life_cycle.death_age = Some(parse_arg(value, "age")?),
+            "--days" => days = Some(parse_arg(value, "day count")?),
+            "--modulus" => modulus = Some(parse_arg(value, "modulus")?),
+            _ => bail!("unknown argument `{}`", arg),
+        }
+    }
+
     println!("--- Day 6: Lanternfish ---");
 
     let input = include_str!("../input.txt");
@@ -14,9 +36,50 @@ fn main() -> Result<()> {
 
     println!("Laternfish after 256 days: {}", population.count());
 
+    if life_cycle != LifeCycle::LANTERNFISH {
+        let mut population = parse_with::<BigUint>(input, life_cycle)?;
+
+        population.simulate(80);
+        println!("Custom fish after 80 days: {}", population.count());
+
+        population.simulate(256 - 80);
+        println!("Custom fish after 256 days: {}", population.count());
+    }
+
+    if let Some(days) = days {
+        let population = parse_with::<BigUint>(input, life_cycle)?;
+
+        match modulus {
+            Some(modulus) => println!(
+                "Fish after {} days (mod {}): {}",
+                days,
+                modulus,
+                population.project_mod(days, modulus)?
+            ),
+            None => println!(
+                "Fish after {} days: {}",
+                days,
+                population.project(days).wrap_err_with(|| format!(
+                    "could not count fish after {} days, try with --modulus",
+                    days
+                ))?
+            ),
+        }
+    }
+
     Ok(())
 }
 
+fn parse_arg<T>(value: &str, what: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    value
+        .parse()
+        .wrap_err_with(|| format!("could not parse `{}` as {}", value, what))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Lanternfish {
     timer: u8,
@@ -28,37 +91,459 @@ impl Lanternfish {
     }
 }
 
+/// How a species of fish reproduces and dies.
+///
+/// Every day, each fish's timer goes down by one. A fish whose timer is 0 instead spawns
+/// `offspring` fish with a timer of `newborn`, and its own timer goes back to `reset`. With a
+/// `death_age`, fish die on the day they would reach that age (after spawning, if it is their
+/// day to); fish present at the start are taken to be newborns, whatever their timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LifeCycle {
+    reset: u8,
+    newborn: u8,
+    offspring: usize,
+    death_age: Option<u32>,
+}
+
+impl LifeCycle {
+    pub const LANTERNFISH: LifeCycle = LifeCycle {
+        reset: 6,
+        newborn: 8,
+        offspring: 1,
+        death_age: None,
+    };
+
+    fn timers(&self) -> usize {
+        self.reset.max(self.newborn) as usize + 1
+    }
+
+    // Ages only need to be told apart when fish can die
+    fn ages(&self) -> usize {
+        self.death_age.map_or(1, |age| age as usize)
+    }
+
+    fn buckets(&self) -> usize {
+        self.timers() * self.ages()
+    }
+
+    fn bucket(&self, timer: usize, age: usize) -> usize {
+        age * self.timers() + timer
+    }
+
+    /// How fish move between buckets in a day, as `(from, to, factor)`: `factor` fish end up in
+    /// bucket `to` for each fish in bucket `from`.
+    fn transitions(&self) -> Vec<(usize, usize, usize)> {
+        let mut transitions = vec![];
+
+        for age in 0..self.ages() {
+            let next_age = if self.death_age.is_some() { age + 1 } else { 0 };
+            let survives = next_age < self.ages();
+
+            for timer in 0..self.timers() {
+                let from = self.bucket(timer, age);
+
+                if timer > 0 {
+                    if survives {
+                        transitions.push((from, self.bucket(timer - 1, next_age), 1));
+                    }
+                } else {
+                    if survives {
+                        transitions.push((from, self.bucket(self.reset as usize, next_age), 1));
+                    }
+                    if self.offspring > 0 {
+                        transitions.push((
+                            from,
+                            self.bucket(self.newborn as usize, 0),
+                            self.offspring,
+                        ));
+                    }
+                }
+            }
+        }
+
+        transitions
+    }
+}
+
+// Every timer and age gets a counter, so bound how many of them a life cycle can need
+const MAX_BUCKETS: usize = 1 << 22;
+
+/// Arithmetic on fish counts and on the matrices projecting them.
+trait Arithmetic: Clone + fmt::Debug + PartialEq {
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn is_zero(&self) -> bool;
+}
+
+/// Type used to count fish, such as `usize`, `u128` or `BigUint`.
+trait Counter: Arithmetic + fmt::Display {
+    fn from_usize(n: usize) -> Self;
+    fn rem_u64(&self, modulus: u64) -> u64;
+    fn bits(&self) -> u64;
+}
+
+// Largest count, in bits, that exact projections are attempted for (128 KiB worth of digits)
+const MAX_EXACT_BITS: u64 = 1 << 20;
+
+macro_rules! impl_counter {
+    ($($t:ty),*) => {
+        $(
+            impl Arithmetic for $t {
+                fn checked_add(&self, other: &Self) -> Option<Self> {
+                    <$t>::checked_add(*self, *other)
+                }
+
+                fn checked_mul(&self, other: &Self) -> Option<Self> {
+                    <$t>::checked_mul(*self, *other)
+                }
+
+                fn is_zero(&self) -> bool {
+                    *self == 0
+                }
+            }
+
+            impl Counter for $t {
+                fn from_usize(n: usize) -> Self {
+                    <$t>::try_from(n).expect("count does not fit")
+                }
+
+                fn rem_u64(&self, modulus: u64) -> u64 {
+                    (*self as u128 % modulus as u128) as u64
+                }
+
+                fn bits(&self) -> u64 {
+                    (<$t>::BITS - self.leading_zeros()) as u64
+                }
+            }
+        )*
+    };
+}
+
+impl_counter!(usize, u64, u128);
+
+impl Arithmetic for BigUint {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.bits() == 0
+    }
+}
+
+impl Counter for BigUint {
+    fn from_usize(n: usize) -> Self {
+        BigUint::from(n)
+    }
+
+    fn rem_u64(&self, modulus: u64) -> u64 {
+        u64::try_from(self % modulus).expect("remainder is below the modulus")
+    }
+
+    fn bits(&self) -> u64 {
+        BigUint::bits(self)
+    }
+}
+
+/// A number modulo `modulus`, which never overflows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Residue {
+    value: u64,
+    modulus: u64,
+}
+
+impl Arithmetic for Residue {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        let value = (self.value as u128 + other.value as u128) % self.modulus as u128;
+        Some(Residue {
+            value: value as u64,
+            ..*self
+        })
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let value = self.value as u128 * other.value as u128 % self.modulus as u128;
+        Some(Residue {
+            value: value as u64,
+            ..*self
+        })
+    }
+
+    fn is_zero(&self) -> bool {
+        self.value == 0
+    }
+}
+
+/// Number of fish per timer (and age, if they can die).
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct Population {
-    timer_counts: [usize; 9],
+struct Population<C = usize> {
+    life_cycle: LifeCycle,
+    counts: Vec<C>,
 }
 
-impl Population {
-    pub fn new(starting_population: Vec<Lanternfish>) -> Self {
-        let mut timer_counts = [0; 9];
+impl<C: Counter> Population<C> {
+    pub fn with_life_cycle(
+        life_cycle: LifeCycle,
+        starting_population: Vec<Lanternfish>,
+    ) -> Result<Self> {
+        ensure!(
+            life_cycle.death_age != Some(0),
+            "fish must live at least one day"
+        );
+        ensure!(
+            life_cycle.buckets() <= MAX_BUCKETS,
+            "fish live too long to be counted by age"
+        );
+
+        let mut counts = vec![0; life_cycle.buckets()];
 
         for fish in starting_population {
-            timer_counts[fish.timer as usize] += 1;
+            ensure!(
+                (fish.timer as usize) < life_cycle.timers(),
+                "timer {} is longer than any in the life cycle",
+                fish.timer
+            );
+
+            counts[life_cycle.bucket(fish.timer as usize, 0)] += 1;
         }
 
-        Self { timer_counts }
+        Ok(Self {
+            life_cycle,
+            counts: counts.into_iter().map(C::from_usize).collect(),
+        })
     }
 
     pub fn simulate(&mut self, days: usize) {
+        let transitions: Vec<_> = self
+            .life_cycle
+            .transitions()
+            .into_iter()
+            .map(|(from, to, factor)| (from, to, C::from_usize(factor)))
+            .collect();
+
         for _ in 0..days {
-            self.timer_counts.rotate_left(1);
-            self.timer_counts[6] = self.timer_counts[6]
-                .checked_add(self.timer_counts[8])
-                .unwrap();
+            self.counts = step(&transitions, &self.counts, &C::from_usize(0))
+                .expect("too many fish for the counter type");
         }
     }
 
-    pub fn count(&self) -> usize {
-        self.timer_counts.iter().sum()
+    pub fn count(&self) -> C {
+        sum(&self.counts).expect("too many fish for the counter type")
+    }
+
+    /// Counts the fish there will be in `days` days, in O(log(days)) matrix products.
+    ///
+    /// Fails early when the count could get too large to compute exactly, which even unbounded
+    /// counters cannot do in reasonable time and memory.
+    pub fn project(&self, days: u64) -> Result<C> {
+        // Each fish turns into at most itself and its offspring every day
+        let growth = ((self.life_cycle.offspring as f64) + 1.0).log2();
+        let bits = self.count().bits() as f64 + days as f64 * growth;
+
+        ensure!(
+            bits <= MAX_EXACT_BITS as f64,
+            "the count could need up to {:.0} bits, project it modulo some number instead",
+            bits
+        );
+
+        project(&self.life_cycle, &self.counts, days, C::from_usize)
+    }
+
+    /// Like [`Population::project`], modulo `modulus`, so that it works for any number of days.
+    pub fn project_mod(&self, days: u64, modulus: u64) -> Result<u64> {
+        ensure!(modulus > 0, "the modulus must be positive");
+
+        let residue = |n: u64| Residue {
+            value: n % modulus,
+            modulus,
+        };
+        let counts: Vec<_> = self
+            .counts
+            .iter()
+            .map(|n| residue(n.rem_u64(modulus)))
+            .collect();
+
+        let count = project(&self.life_cycle, &counts, days, |n| residue(n as u64))?;
+        Ok(count.value)
+    }
+}
+
+fn sum<T: Arithmetic>(numbers: &[T]) -> Option<T> {
+    let (first, rest) = numbers.split_first()?;
+    rest.iter()
+        .try_fold(first.clone(), |sum, n| sum.checked_add(n))
+}
+
+/// Moves the counts forward by a day.
+fn step<T: Arithmetic>(
+    transitions: &[(usize, usize, T)],
+    counts: &[T],
+    zero: &T,
+) -> Option<Vec<T>> {
+    let mut next = vec![zero.clone(); counts.len()];
+
+    for (from, to, factor) in transitions {
+        next[*to] = counts[*from].checked_mul(factor)?.checked_add(&next[*to])?;
+    }
+
+    Some(next)
+}
+
+// Life cycles with more buckets than this are not projected with matrices (2 MiB of u64 cells)
+const MAX_MATRIX_BUCKETS: usize = 512;
+
+// Give up on projecting day by day beyond this many transitions in total
+const MAX_STEPS: u64 = 1 << 32;
+
+fn project<T: Arithmetic>(
+    life_cycle: &LifeCycle,
+    counts: &[T],
+    days: u64,
+    convert: impl Fn(usize) -> T,
+) -> Result<T> {
+    let overflow = || eyre!("too many fish to count");
+
+    let buckets = life_cycle.buckets() as u64;
+    let transitions: Vec<_> = life_cycle
+        .transitions()
+        .into_iter()
+        .map(|(from, to, factor)| (from, to, convert(factor)))
+        .collect();
+
+    // Going day by day is cheaper for few days or matrices too large to hold
+    let steps = days.saturating_mul(transitions.len() as u64);
+    let products = 2 * (64 - days.leading_zeros()) as u64;
+    let matrix_steps = buckets.saturating_pow(3).saturating_mul(products);
+
+    if buckets as usize > MAX_MATRIX_BUCKETS || steps <= matrix_steps {
+        ensure!(
+            steps <= MAX_STEPS,
+            "a life cycle with {} buckets is too large to project {} days ahead",
+            buckets,
+            days
+        );
+
+        let zero = convert(0);
+        let mut counts = counts.to_vec();
+        for _ in 0..days {
+            counts = step(&transitions, &counts, &zero).ok_or_else(overflow)?;
+        }
+
+        return sum(&counts).ok_or_else(overflow);
+    }
+
+    Matrix::transitions(life_cycle, convert)
+        .pow(days)
+        .and_then(|matrix| matrix.apply(counts))
+        .and_then(|counts| sum(&counts))
+        .ok_or_else(overflow)
+}
+
+/// Square matrix mapping bucket counts to the counts some days later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Matrix<T> {
+    size: usize,
+    // Row-major, rows being the buckets fish end up in
+    cells: Vec<T>,
+    zero: T,
+    one: T,
+}
+
+impl<T: Arithmetic> Matrix<T> {
+    fn identity(&self) -> Self {
+        let mut cells = vec![self.zero.clone(); self.size * self.size];
+        for i in 0..self.size {
+            cells[i * self.size + i] = self.one.clone();
+        }
+
+        Matrix {
+            cells,
+            ..self.clone()
+        }
+    }
+
+    /// The matrix of a single day.
+    fn transitions(life_cycle: &LifeCycle, convert: impl Fn(usize) -> T) -> Self {
+        let size = life_cycle.buckets();
+        let mut factors = vec![0; size * size];
+
+        for (from, to, factor) in life_cycle.transitions() {
+            factors[to * size + from] += factor;
+        }
+
+        Matrix {
+            size,
+            cells: factors.into_iter().map(&convert).collect(),
+            zero: convert(0),
+            one: convert(1),
+        }
+    }
+
+    fn mul(&self, other: &Matrix<T>) -> Option<Matrix<T>> {
+        let n = self.size;
+        let mut cells = vec![self.zero.clone(); n * n];
+
+        for i in 0..n {
+            for k in 0..n {
+                let a = &self.cells[i * n + k];
+                if a.is_zero() {
+                    continue;
+                }
+
+                for j in 0..n {
+                    let product = a.checked_mul(&other.cells[k * n + j])?;
+                    cells[i * n + j] = cells[i * n + j].checked_add(&product)?;
+                }
+            }
+        }
+
+        Some(Matrix {
+            cells,
+            ..self.clone()
+        })
+    }
+
+    /// Raises the matrix to the `n`-th power by repeated squaring.
+    fn pow(&self, mut n: u64) -> Option<Matrix<T>> {
+        let mut result = self.identity();
+        let mut base = self.clone();
+
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.mul(&base)?;
+            }
+
+            n >>= 1;
+            if n > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+
+        Some(result)
+    }
+
+    fn apply(&self, counts: &[T]) -> Option<Vec<T>> {
+        let n = self.size;
+
+        (0..n)
+            .map(|i| {
+                (0..n).try_fold(self.zero.clone(), |sum, j| {
+                    sum.checked_add(&self.cells[i * n + j].checked_mul(&counts[j])?)
+                })
+            })
+            .collect()
     }
 }
 
 fn parse(input: &str) -> Result<Population> {
+    parse_with(input, LifeCycle::LANTERNFISH)
+}
+
+fn parse_with<C: Counter>(input: &str, life_cycle: LifeCycle) -> Result<Population<C>> {
     let fish: Result<Vec<Lanternfish>> = input
         .trim()
         .split(',')
@@ -70,7 +555,7 @@ fn parse(input: &str) -> Result<Population> {
         })
         .collect();
 
-    Ok(Population::new(fish?))
+    Population::with_life_cycle(life_cycle, fish?)
 }
 
 #[cfg(test)]
@@ -85,13 +570,17 @@ mod tests {
 
         assert_eq!(
             population,
-            Population::new(vec![
-                Lanternfish::new(3),
-                Lanternfish::new(4),
-                Lanternfish::new(3),
-                Lanternfish::new(1),
-                Lanternfish::new(2),
-            ])
+            Population::with_life_cycle(
+                LifeCycle::LANTERNFISH,
+                vec![
+                    Lanternfish::new(3),
+                    Lanternfish::new(4),
+                    Lanternfish::new(3),
+                    Lanternfish::new(1),
+                    Lanternfish::new(2),
+                ]
+            )
+            .unwrap()
         );
     }
 
@@ -117,6 +606,217 @@ mod tests {
         assert_eq!(population.count(), 26984457539);
     }
 
+    // Simulates each fish one by one, as `(timer, age)`
+    fn simulate_naive(life_cycle: LifeCycle, timers: &[u8], days: usize) -> usize {
+        let mut fish: Vec<(u8, u32)> = timers.iter().map(|&timer| (timer, 0)).collect();
+
+        for _ in 0..days {
+            let mut next = vec![];
+
+            for &(timer, age) in &fish {
+                let timer = if timer == 0 {
+                    next.extend(vec![(life_cycle.newborn, 0); life_cycle.offspring]);
+                    life_cycle.reset
+                } else {
+                    timer - 1
+                };
+
+                if life_cycle.death_age != Some(age + 1) {
+                    next.push((timer, age + 1));
+                }
+            }
+
+            fish = next;
+        }
+
+        fish.len()
+    }
+
+    #[test]
+    fn supports_other_life_cycles() {
+        let timers = [3, 4, 3, 1, 2, 0, 5];
+        let life_cycles = [
+            LifeCycle::LANTERNFISH,
+            LifeCycle {
+                reset: 2,
+                newborn: 4,
+                offspring: 2,
+                death_age: None,
+            },
+            LifeCycle {
+                reset: 5,
+                newborn: 3,
+                offspring: 3,
+                death_age: Some(11),
+            },
+            LifeCycle {
+                reset: 0,
+                newborn: 0,
+                offspring: 1,
+                death_age: Some(1),
+            },
+            LifeCycle {
+                reset: 6,
+                newborn: 8,
+                offspring: 0,
+                death_age: Some(4),
+            },
+        ];
+
+        for life_cycle in life_cycles {
+            let timers: Vec<u8> = timers
+                .iter()
+                .copied()
+                .filter(|&t| (t as usize) < life_cycle.timers())
+                .collect();
+            let fish = timers.iter().map(|&t| Lanternfish::new(t)).collect();
+            let mut population: Population = Population::with_life_cycle(life_cycle, fish).unwrap();
+
+            for day in 1..=30 {
+                population.simulate(1);
+                assert_eq!(
+                    population.count(),
+                    simulate_naive(life_cycle, &timers, day),
+                    "{:?} on day {}",
+                    life_cycle,
+                    day
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn projects_populations() {
+        let mut population = parse(SAMPLE).unwrap();
+
+        assert_eq!(population.project(0).unwrap(), 5);
+        assert_eq!(population.project(18).unwrap(), 26);
+        assert_eq!(population.project(256).unwrap(), 26984457539);
+        assert_eq!(
+            population.project_mod(256, 1_000_000_007).unwrap(),
+            984457357
+        );
+        assert_eq!(population.project_mod(256, 1).unwrap(), 0);
+        assert!(population.project_mod(256, 0).is_err());
+
+        population.simulate(100);
+        assert_eq!(population.project(156).unwrap(), 26984457539);
+
+        let life_cycle = LifeCycle {
+            reset: 5,
+            newborn: 3,
+            offspring: 3,
+            death_age: Some(11),
+        };
+        let fish = [3, 1, 0].into_iter().map(Lanternfish::new).collect();
+        let mut population: Population = Population::with_life_cycle(life_cycle, fish).unwrap();
+        let projected = population.project(40).unwrap();
+
+        population.simulate(40);
+        assert_eq!(projected, population.count());
+    }
+
+    #[test]
+    fn projects_populations_very_far() {
+        const P: u64 = 1_000_000_007;
+
+        let population = parse(SAMPLE).unwrap();
+        let days = 1_000_000_000_000_000_000;
+
+        assert!(population.project(days).is_err());
+
+        // A day of lanternfish has x^9 - x^2 - 1 as characteristic polynomial, so by
+        // Cayley-Hamilton the counts follow the matching recurrence
+        let count = |days| population.project_mod(days, P).unwrap();
+        assert_eq!(count(days), (count(days - 7) + count(days - 9)) % P);
+    }
+
+    #[test]
+    fn counts_beyond_usize() {
+        let mut population: Population<u128> = parse_with(SAMPLE, LifeCycle::LANTERNFISH).unwrap();
+
+        population.simulate(998);
+        assert_eq!(population.count(), 318892759258767436907541348343071883668);
+        assert!(population.project(1).is_err());
+
+        let mut population: Population<BigUint> =
+            parse_with(SAMPLE, LifeCycle::LANTERNFISH).unwrap();
+        let projected = population.project(5000).unwrap();
+
+        population.simulate(1000);
+        assert_eq!(
+            population.count().to_string(),
+            "379589061144698259131825683795505058481"
+        );
+
+        population.simulate(4000);
+        assert_eq!(population.count(), projected);
+        assert_eq!(projected.to_string().len(), 190);
+        assert_eq!(
+            projected.rem_u64(1_000_000_007),
+            parse(SAMPLE)
+                .unwrap()
+                .project_mod(5000, 1_000_000_007)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn projects_long_lived_fish_without_matrices() {
+        let life_cycle = LifeCycle {
+            death_age: Some(10_000),
+            ..LifeCycle::LANTERNFISH
+        };
+        let mut population: Population = parse_with(SAMPLE, life_cycle).unwrap();
+
+        let projected = population.project(80).unwrap();
+        let projected_mod = population.project_mod(80, 1000).unwrap();
+        assert!(population.project(1_000_000_000_000).is_err());
+        assert!(population.project_mod(1_000_000_000_000, 1000).is_err());
+
+        population.simulate(80);
+        assert_eq!(projected, population.count());
+        assert_eq!(projected_mod, 5934 % 1000);
+    }
+
+    #[test]
+    fn refuses_exact_projections_too_large_to_compute() {
+        let population: Population<BigUint> = parse_with(SAMPLE, LifeCycle::LANTERNFISH).unwrap();
+
+        assert!(population.project(1_000_000_000_000_000_000).is_err());
+        assert!(population
+            .project_mod(1_000_000_000_000_000_000, 1_000_000_007)
+            .is_ok());
+
+        let count = population.project(100_000).unwrap();
+        assert_eq!(
+            count.rem_u64(1_000_000_007),
+            population.project_mod(100_000, 1_000_000_007).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_life_cycles() {
+        let short = LifeCycle {
+            reset: 2,
+            newborn: 3,
+            ..LifeCycle::LANTERNFISH
+        };
+        assert!(parse_with::<usize>(SAMPLE, short).is_err());
+
+        let immortal = LifeCycle {
+            death_age: Some(0),
+            ..LifeCycle::LANTERNFISH
+        };
+        assert!(parse_with::<usize>(SAMPLE, immortal).is_err());
+
+        let ancient = LifeCycle {
+            death_age: Some(2_000_000_000),
+            ..LifeCycle::LANTERNFISH
+        };
+        assert!(parse_with::<usize>(SAMPLE, ancient).is_err());
+    }
+
     #[test]
     fn does_not_regress() {
         let input = include_str!("../input.txt");